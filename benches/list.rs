@@ -0,0 +1,52 @@
+//! Compares walking a list with `cdr`, which shares the list's storage,
+//! against copying the rest of the list into a new `Vec` at each step as
+//! `cdr` used to. Run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+use lisp::stdlib::list::{car, cdr, list};
+use lisp::types::Val;
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn report(name: &str, n: usize, steps: usize, d: Duration) {
+    let per_step = d / steps as u32;
+    println!("{:<24} n={:<7} {:>10.2?} total {:>10.2?}/step", name, n, d, per_step);
+}
+
+fn main() {
+    // The copying walk is quadratic, so only time its first steps.
+    let steps = 1_000;
+    for &n in &[1_000, 10_000, 100_000] {
+        let items: Vec<Val> = (0..n as i64).map(Val::Int).collect();
+        let l = list(items.clone()).unwrap();
+        let d = time(|| {
+            let mut l = l.clone();
+            for _ in 0..n {
+                car(vec![l.clone()]).unwrap();
+                l = cdr(vec![l]).unwrap();
+            }
+        });
+        report("car + cdr, whole list", n, n, d);
+        let d = time(|| {
+            let mut l = l.clone();
+            for _ in 0..steps {
+                car(vec![l.clone()]).unwrap();
+                l = cdr(vec![l]).unwrap();
+            }
+        });
+        report("car + cdr", n, steps, d);
+        let d = time(|| {
+            let mut v = items.clone();
+            for _ in 0..steps {
+                let _ = v[0].clone();
+                v = v[1..].to_vec();
+            }
+        });
+        report("car + copying cdr", n, steps, d);
+    }
+}
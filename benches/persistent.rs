@@ -0,0 +1,65 @@
+//! Compares updating persistent collections against cloning a `Vec<Val>` or
+//! `HashMap<Val, Val>` per update. Run with `cargo bench`.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use lisp::persistent::{PMap, PVector};
+use lisp::types::Val;
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn report(name: &str, n: usize, updates: usize, d: Duration) {
+    let per_update = d / updates as u32;
+    println!("{:<24} n={:<7} {:>10.2?} total {:>10.2?}/update", name, n, d, per_update);
+}
+
+// Val's Hash and Eq use a vector's identity, not its contents, so the keys
+// can't change while in the map.
+#[allow(clippy::mutable_key_type)]
+fn main() {
+    let updates = 1_000;
+    for &n in &[1_000, 10_000, 100_000] {
+        let vec: Vec<Val> = (0..n as i64).map(Val::Int).collect();
+        let pvec: PVector<Val> = vec.iter().cloned().collect();
+        let d = time(|| {
+            let mut v = pvec.clone();
+            for i in 0..updates {
+                v = v.set(i * 7 % n, Val::Int(0)).unwrap();
+            }
+        });
+        report("PVector::set", n, updates, d);
+        let d = time(|| {
+            let mut v = vec.clone();
+            for i in 0..updates {
+                let mut next = v.clone();
+                next[i * 7 % n] = Val::Int(0);
+                v = next;
+            }
+        });
+        report("Vec clone + set", n, updates, d);
+
+        let map: HashMap<Val, Val> = vec.iter().map(|v| (v.clone(), v.clone())).collect();
+        let pmap: PMap<Val, Val> = map.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        let d = time(|| {
+            let mut m = pmap.clone();
+            for i in 0..updates {
+                m = m.insert(Val::Int((i * 7 % n) as i64), Val::Int(0));
+            }
+        });
+        report("PMap::insert", n, updates, d);
+        let d = time(|| {
+            let mut m = map.clone();
+            for i in 0..updates {
+                let mut next = m.clone();
+                next.insert(Val::Int((i * 7 % n) as i64), Val::Int(0));
+                m = next;
+            }
+        });
+        report("HashMap clone + insert", n, updates, d);
+    }
+}
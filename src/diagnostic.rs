@@ -0,0 +1,95 @@
+use std::fmt;
+use std::rc::Rc;
+
+use super::errors::ErrType;
+
+/// A region of source text. Lines and columns are 1-based and count chars;
+/// `end_col` is exclusive.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+/// Renders `err` with the offending line of `source`, the contents of `file`,
+/// and a caret underline beneath its span, followed by its backtrace if it
+/// has one. Spans covering several lines are underlined to the end of their
+/// first line. Errors without a span in `file` are rendered as their message.
+pub fn render(err: &ErrType, file: &str, source: &str) -> String {
+    match err.backtrace() {
+        Some(bt) => format!("{}\n{}", render_located(err.untraced(), file, source), bt),
+        None => render_located(err, file, source),
+    }
+}
+
+fn render_located(err: &ErrType, file: &str, source: &str) -> String {
+    let span = match err.span() {
+        Some(span) if &*span.file == file => span,
+        _ => return err.to_string(),
+    };
+    let text = match source.lines().nth(span.line - 1) {
+        Some(text) => text,
+        None => return err.to_string(),
+    };
+    let end = if span.end_line == span.line {
+        span.end_col
+    } else {
+        text.chars().count() + 1
+    };
+    let gutter = span.line.to_string().len();
+    let indent: String = text.chars()
+        .take(span.col - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    format!(
+        "{}\n{:w$} |\n{} | {}\n{:w$} | {}{}",
+        err, "", span.line, text, "", indent, "^".repeat(end.saturating_sub(span.col).max(1)),
+        w = gutter,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(line: usize, col: usize, end_line: usize, end_col: usize) -> Span {
+        Span { file: Rc::from("test.lisp"), line, col, end_line, end_col }
+    }
+
+    #[test]
+    fn test_render() {
+        let src = "(car 1)\n(+ 1 (list 2))\n";
+        let err = ErrType::type_error("int", "list").at(span(2, 6, 2, 14));
+        let expected = "test.lisp:2:6: Wrong type: expected int, got list\n  |\n2 | (+ 1 (list 2))\n  |      ^^^^^^^^";
+        assert_eq!(render(&err, "test.lisp", src), expected);
+    }
+
+    #[test]
+    fn test_render_multiline() {
+        let src = "(+ 1\n   2)";
+        let err = ErrType::lookup("x").at(span(1, 1, 2, 6));
+        let expected = "test.lisp:1:1: x not found\n  |\n1 | (+ 1\n  | ^^^^";
+        assert_eq!(render(&err, "test.lisp", src), expected);
+    }
+
+    #[test]
+    fn test_render_other_file() {
+        let err = ErrType::lookup("x").at(span(1, 1, 1, 2));
+        assert_eq!(render(&err, "other.lisp", "x"), "test.lisp:1:1: x not found");
+    }
+
+    #[test]
+    fn test_render_no_span() {
+        let err = ErrType::lookup("x");
+        assert_eq!(render(&err, "test.lisp", ""), "x not found");
+    }
+}
@@ -1,20 +1,96 @@
+use std::cell::{Ref, RefCell};
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use super::types::{F, Val};
+use std::convert::TryFrom;
+use std::fmt;
+use std::mem;
+use std::rc::{Rc, Weak};
+use super::reader::{Form, FormKind, Forms};
+use super::types::{Args, F, Record, RecordType, Res, Val};
 use super::errors::ErrType;
+use super::gc::{self, Tracked};
+use super::pattern::Pattern;
+use super::stdlib::Register;
+use super::symbol::Symbol;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Arity {
     NoArgs,
     SomeArgs(u8),
+    AtLeast(u8),
+    Range(u8, u8),
     VarArgs
 }
 
-#[derive(Debug)]
+impl Arity {
+    /// The arity of a call site passing `n` arguments.
+    pub fn of(n: usize) -> Arity {
+        match n {
+            0 => Arity::NoArgs,
+            n => u8::try_from(n).map_or(Arity::AtLeast(u8::MAX), Arity::SomeArgs),
+        }
+    }
+    pub(crate) fn bounds(&self) -> (usize, Option<usize>) {
+        match *self {
+            Arity::NoArgs => (0, Some(0)),
+            Arity::SomeArgs(n) => (n as usize, Some(n as usize)),
+            Arity::AtLeast(n) => (n as usize, None),
+            Arity::Range(min, max) => (min as usize, Some(max as usize)),
+            Arity::VarArgs => (0, None),
+        }
+    }
+    pub fn accepts(&self, n: usize) -> bool {
+        let (min, max) = self.bounds();
+        n >= min && max.is_none_or(|max| n <= max)
+    }
+    fn covers(&self, other: &Arity) -> bool {
+        let (min, max) = self.bounds();
+        let (o_min, o_max) = other.bounds();
+        min <= o_min && match (max, o_max) {
+            (None, _) => true,
+            (Some(max), Some(o_max)) => o_max <= max,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::NoArgs => write!(f, "0"),
+            Arity::SomeArgs(n) => write!(f, "{}", n),
+            Arity::AtLeast(n) => write!(f, "at least {}", n),
+            Arity::Range(min, max) => write!(f, "{} to {}", min, max),
+            Arity::VarArgs => write!(f, "any number"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Func(HashMap<Arity, F>);
 
 impl Func {
+    /// Finds the most specific implementation accepting `arity`: the one
+    /// with the narrowest range, preferring the higher minimum on ties.
     pub fn lookup(&self, arity: Arity) -> Option<&F> {
-        self.0.get(&arity).or_else(|| self.0.get(&Arity::VarArgs))
+        self.0.get(&arity).or_else(|| {
+            self.0.iter()
+                .filter(|(a, _)| a.covers(&arity))
+                .min_by_key(|(a, _)| {
+                    let (min, max) = a.bounds();
+                    (max.map_or(usize::MAX, |max| max - min), Reverse(min))
+                })
+                .map(|(_, f)| f)
+        })
+    }
+    pub fn call(&self, name: &str, args: Args) -> Res {
+        match self.lookup(Arity::of(args.len())) {
+            Some(f) => f(args),
+            None => {
+                let expected: Vec<Arity> = self.0.keys().copied().collect();
+                Err(ErrType::arity_mismatch(name, &expected, args.len()))
+            }
+        }
     }
     pub fn new(arity: Arity, f: F) -> Self {
         let mut m = HashMap::new();
@@ -26,10 +102,79 @@ impl Func {
     }
 }
 
-#[derive(Debug)]
+/// A procedure defined in Lisp, called in a child of the environment it was
+/// defined in.
+#[derive(Clone, Debug)]
+pub struct Lambda {
+    pub params: Params,
+    pub body: Forms,
+}
+
+/// A procedure generated by `define-record-type`. Field positions index
+/// into the record type's fields.
+#[derive(Clone, Debug)]
+pub enum RecordProc {
+    Constructor(Rc<RecordType>, Vec<usize>),
+    Predicate(Rc<RecordType>),
+    Accessor(Rc<RecordType>, usize),
+    Modifier(Rc<RecordType>, usize),
+}
+
+impl RecordProc {
+    fn arity(&self) -> Arity {
+        match self {
+            RecordProc::Constructor(_, fields) => Arity::of(fields.len()),
+            RecordProc::Predicate(_) | RecordProc::Accessor(..) => Arity::SomeArgs(1),
+            RecordProc::Modifier(..) => Arity::SomeArgs(2),
+        }
+    }
+    pub fn call(&self, name: &str, args: Args) -> Res {
+        let arity = self.arity();
+        if !arity.accepts(args.len()) {
+            return Err(ErrType::arity_mismatch(name, &[arity], args.len()));
+        }
+        match self {
+            RecordProc::Constructor(rtype, positions) => {
+                let mut fields = vec![Val::Bool(false); rtype.fields.len()];
+                for (&i, v) in positions.iter().zip(args) {
+                    fields[i] = v;
+                }
+                let r = Rc::new(Record { rtype: Rc::clone(rtype), fields: fields.into() });
+                gc::track(Tracked::Record(Rc::downgrade(&r)));
+                Ok(Val::Record(r))
+            }
+            RecordProc::Predicate(rtype) => {
+                Ok(Val::Bool(matches!(&args[0], Val::Record(r) if Rc::ptr_eq(&r.rtype, rtype))))
+            }
+            RecordProc::Accessor(rtype, i) => Ok(args[0].unwrap_record(rtype)?.fields.borrow()[*i].clone()),
+            RecordProc::Modifier(rtype, i) => {
+                args[0].unwrap_record(rtype)?.fields.borrow_mut()[*i] = args[1].clone();
+                Ok(Val::Nil)
+            }
+        }
+    }
+}
+
+/// A procedure the evaluator runs itself, because it works on the
+/// evaluator's stack or search path rather than on its arguments alone.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Primitive {
+    /// `(invoke-restart name arg...)`
+    InvokeRestart,
+    /// `(load "file")`
+    #[cfg(feature = "io")]
+    Load,
+}
+
+#[derive(Clone, Debug)]
 pub enum Entry {
     Val(Val),
     Func(Func),
+    Lambda(Rc<Lambda>),
+    Record(RecordProc),
+    Primitive(Primitive),
+    /// A name imported from a module: the module, and the name it exports.
+    Import(Symbol, Symbol),
 }
 
 impl Entry {
@@ -49,19 +194,195 @@ impl Entry {
     }
 }
 
-pub struct Scope<'a>(HashMap<&'a str, Entry>);
+/// A procedure used as a value, as when a name bound to one is evaluated.
+/// `entry` is a `Func`, `Lambda`, `Record` or `Primitive` entry, and `env` the environment
+/// it was found in, which a lambda's body is evaluated in a child of.
+#[derive(Clone)]
+pub struct Procedure {
+    pub name: Symbol,
+    pub entry: Entry,
+    pub env: Env,
+}
 
-pub struct Env<'a> {
-    scope: Scope<'a>,
-    parent: Option<&'a Env<'a>>,
+impl Procedure {
+    /// Makes a procedure, tracked by the collector since `env` may come to
+    /// hold it.
+    pub fn new(name: Symbol, entry: Entry, env: Env) -> Rc<Procedure> {
+        let p = Rc::new(Procedure { name, entry, env });
+        gc::track(Tracked::Procedure(Rc::downgrade(&p)));
+        p
+    }
 }
 
-impl<'a, 'b> Env<'a> {
-    pub fn register(&mut self, name: &'a str, v: Val) {
-        self.scope.0.insert(name, Entry::Val(v));
+impl fmt::Debug for Procedure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#<procedure {}>", self.name)
+    }
+}
+
+/// A parsed lambda list, e.g. `(a (b c) #!optional (d (+ a 1)) e . rest)`.
+/// Required parameters may be list patterns, as in `match`, which destructure
+/// their argument. An optional parameter's default is an expression, evaluated
+/// when the argument is missing; without one, the parameter is bound to `#f`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Params {
+    required: Vec<Pattern>,
+    optional: Vec<(Symbol, Option<Form>)>,
+    rest: Option<Symbol>,
+    arity: Arity,
+}
+
+impl Params {
+    const OPTIONAL: &'static str = "#!optional";
+    const REST: &'static str = ".";
+
+    pub fn parse(spec: &[Form]) -> Result<Params, ErrType> {
+        let mut required = Vec::new();
+        let mut optional = Vec::new();
+        let mut rest = None;
+        let mut in_optional = false;
+        let mut iter = spec.iter().map(|f| (f, f.to_val()));
+        while let Some((f, v)) = iter.next() {
+            match &f.kind {
+                FormKind::List(_) if !in_optional => {
+                    required.push(Pattern::parse_binding(f)?);
+                    continue;
+                }
+                FormKind::List(l) if l.len() == 2 => {
+                    optional.push((*l[0].to_val().unwrap_symbol()?, Some(l[1].clone())));
+                    continue;
+                }
+                FormKind::List(_) => return Err(ErrType::syntax("malformed parameter list")),
+                _ => (),
+            }
+            let sym = *v.unwrap_symbol()?;
+            match sym.as_str() {
+                Params::OPTIONAL if !in_optional => in_optional = true,
+                Params::REST => {
+                    rest = Some(*iter.next()
+                        .ok_or_else(|| ErrType::syntax("missing rest parameter"))?
+                        .1.unwrap_symbol()?);
+                    if iter.next().is_some() {
+                        return Err(ErrType::syntax("rest parameter must be last"));
+                    }
+                }
+                Params::OPTIONAL => return Err(ErrType::syntax("duplicate #!optional")),
+                _ if in_optional => optional.push((sym, None)),
+                _ => required.push(Pattern::bind(sym)),
+            }
+        }
+        let too_many = || ErrType::syntax("too many parameters");
+        let min = u8::try_from(required.len()).map_err(|_| too_many())?;
+        let max = u8::try_from(required.len() + optional.len()).map_err(|_| too_many())?;
+        let arity = match (rest, min, max) {
+            (Some(_), 0, _) => Arity::VarArgs,
+            (Some(_), min, _) => Arity::AtLeast(min),
+            (None, 0, 0) => Arity::NoArgs,
+            (None, min, max) if min == max => Arity::SomeArgs(min),
+            (None, min, max) => Arity::Range(min, max),
+        };
+        Ok(Params { required, optional, rest, arity })
+    }
+    pub fn arity(&self) -> Arity {
+        self.arity
+    }
+}
+
+#[derive(Default)]
+pub struct Scope(HashMap<Symbol, Entry>);
+
+impl Scope {
+    pub fn get(&self, name: Symbol) -> Option<&Entry> {
+        self.0.get(&name)
+    }
+    /// The values bound in this scope, leaving out procedures and imports.
+    pub fn values(&self) -> impl Iterator<Item = &Val> {
+        self.0.values().filter_map(Entry::unwrap_val)
+    }
+}
+
+/// A scope of bindings and the environment enclosing it. Environments are
+/// shared: a procedure keeps the one it was defined in, and a continuation
+/// those of the calls it returns to, so cloning an `Env` doesn't copy it.
+#[derive(Clone)]
+pub struct Env(Rc<Node>);
+
+struct Node {
+    scope: RefCell<Scope>,
+    parent: Option<Env>,
+    /// Stdlib modules not registered yet; see `Env::lazy`.
+    pending: RefCell<Pending>,
+}
+
+/// An environment the collector knows of, which this doesn't keep alive.
+pub struct WeakEnv(Weak<Node>);
+
+impl WeakEnv {
+    pub fn upgrade(&self) -> Option<Env> {
+        self.0.upgrade().map(Env)
+    }
+    pub fn is_live(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+}
+
+/// The modules of a lazy environment, each taken out once registered, and
+/// the index of the module binding each of their names.
+#[derive(Default)]
+struct Pending {
+    modules: Vec<Option<Register>>,
+    owners: HashMap<Symbol, usize>,
+}
+
+impl Env {
+    pub fn register(&self, name: &str, v: Val) {
+        self.register_symbol(Symbol::intern(name), v);
+    }
+    pub fn register_symbol(&self, name: Symbol, v: Val) {
+        self.insert(name, Entry::Val(v));
+    }
+    fn insert(&self, name: Symbol, entry: Entry) {
+        self.0.scope.borrow_mut().0.insert(name, entry);
+    }
+    /// Binds `args` to `params` in this scope, as when calling `name`, and
+    /// returns the optional parameters left without an argument. Their
+    /// defaults are for the caller to evaluate and bind.
+    pub fn bind<'p>(&self, name: &str, params: &'p Params, args: Args)
+        -> Result<&'p [(Symbol, Option<Form>)], ErrType> {
+        if !params.arity.accepts(args.len()) {
+            return Err(ErrType::arity_mismatch(name, &[params.arity], args.len()));
+        }
+        let supplied = args.len().saturating_sub(params.required.len()).min(params.optional.len());
+        let mut args = args.into_iter();
+        for (p, v) in params.required.iter().zip(&mut args) {
+            for (s, v) in p.destructure(&v)? {
+                self.register_symbol(s, v);
+            }
+        }
+        for ((s, _), v) in params.optional.iter().zip(&mut args) {
+            self.register_symbol(*s, v);
+        }
+        if let Some(s) = params.rest {
+            self.register_symbol(s, Val::List(args.collect::<Vec<_>>().into()));
+        }
+        Ok(&params.optional[supplied..])
+    }
+    pub fn register_lambda(&self, name: Symbol, l: Lambda) {
+        self.insert(name, Entry::Lambda(Rc::new(l)));
+    }
+    pub fn register_import(&self, name: Symbol, module: Symbol, exported: Symbol) {
+        self.insert(name, Entry::Import(module, exported));
+    }
+    pub fn register_record_proc(&self, name: Symbol, p: RecordProc) {
+        self.insert(name, Entry::Record(p));
+    }
+    pub fn register_primitive(&self, name: &str, p: Primitive) {
+        self.insert(Symbol::intern(name), Entry::Primitive(p));
     }
-    pub fn register_func(&mut self, name: &'a str, arity: Arity, f: F) -> Option<ErrType> {
-        if let Some(ref mut existing) = self.scope.0.get_mut(name) {
+    pub fn register_func(&self, name: &str, arity: Arity, f: F) -> Option<ErrType> {
+        let sym = Symbol::intern(name);
+        let mut scope = self.0.scope.borrow_mut();
+        if let Some(existing) = scope.0.get_mut(&sym) {
             if let Entry::Func(ef) = existing {
                 ef.add_arity(arity, f);
                 None
@@ -69,22 +390,118 @@ impl<'a, 'b> Env<'a> {
                 Some(ErrType::not_a_function(name))
             }
         } else {
-            self.scope.0.insert(name, Entry::Func(Func::new(arity, f)));
+            scope.0.insert(sym, Entry::Func(Func::new(arity, f)));
             None
         }
     }
-    pub fn lookup(&'a self, name: &'a str) -> Result<&'a Entry, ErrType> {
-        if let Some(v) = self.scope.0.get(name) {
-            Ok(v)
-        } else {
-            self.parent.map_or(Err(ErrType::lookup(name)), |p| p.lookup(name))
+    pub fn lookup(&self, name: &str) -> Result<Ref<'_, Entry>, ErrType> {
+        self.lookup_symbol(Symbol::intern(name))
+    }
+    pub fn lookup_symbol(&self, name: Symbol) -> Result<Ref<'_, Entry>, ErrType> {
+        self.find(name).map(|(e, _)| e)
+    }
+    /// Like `lookup_symbol`, but also returns the environment `name` was
+    /// found in. The entry can't be rebound while it's borrowed.
+    pub fn find(&self, name: Symbol) -> Result<(Ref<'_, Entry>, &Env), ErrType> {
+        loop {
+            let scope = self.0.scope.borrow();
+            if scope.0.contains_key(&name) {
+                return Ok((Ref::map(scope, |s| &s.0[&name]), self));
+            }
+            drop(scope);
+            if let Some(p) = &self.0.parent {
+                return p.find(name);
+            }
+            if !self.register_pending(name) {
+                return Err(ErrType::lookup(name.as_str()));
+            }
         }
     }
-    pub fn new(parent: Option<&'a Env<'a>>) -> Env<'a> {
-        Env {
-            scope: Scope(HashMap::new()),
-            parent
+    /// Registers the pending module binding `name`, returning false if there
+    /// isn't one. Its builtins don't replace names bound meanwhile.
+    fn register_pending(&self, name: Symbol) -> bool {
+        let register = {
+            let mut pending = self.0.pending.borrow_mut();
+            match pending.owners.get(&name).copied() {
+                Some(i) => pending.modules[i].take(),
+                None => None,
+            }
+        };
+        let Some(register) = register else {
+            return false;
+        };
+        let module = Env::new(None);
+        register(&module);
+        let mut scope = self.0.scope.borrow_mut();
+        for (name, entry) in module.0.scope.take().0 {
+            scope.0.entry(name).or_insert(entry);
         }
+        true
+    }
+    /// Whether `name` is bound in this scope, ignoring parents.
+    pub fn defines(&self, name: Symbol) -> bool {
+        self.0.scope.borrow().0.contains_key(&name)
+    }
+    /// The names bound in this scope, ignoring parents, in no particular order.
+    pub fn names(&self) -> Vec<Symbol> {
+        self.0.scope.borrow().0.keys().copied().collect()
+    }
+    /// The outermost environment, which holds the builtins.
+    pub fn root(&self) -> &Env {
+        let mut env = self;
+        while let Some(p) = &env.0.parent {
+            env = p;
+        }
+        env
+    }
+    pub fn parent(&self) -> Option<&Env> {
+        self.0.parent.as_ref()
+    }
+    /// Where this environment lives, which identifies it.
+    pub fn as_ptr(&self) -> *const () {
+        Rc::as_ptr(&self.0).cast()
+    }
+    /// How many references to this environment there are.
+    pub fn strong_count(&self) -> usize {
+        Rc::strong_count(&self.0)
+    }
+    pub fn downgrade(&self) -> WeakEnv {
+        WeakEnv(Rc::downgrade(&self.0))
+    }
+    /// This scope's bindings, unless they're being changed.
+    pub fn try_scope(&self) -> Option<Ref<'_, Scope>> {
+        self.0.scope.try_borrow().ok()
+    }
+    /// Unbinds everything in this scope, returning what was bound, unless
+    /// the bindings are borrowed.
+    pub fn take_scope(&self) -> Option<Scope> {
+        self.0.scope.try_borrow_mut().ok().map(|mut s| mem::take(&mut *s))
+    }
+    /// Makes an environment binding what `scope` does.
+    pub fn with_scope(scope: Scope, parent: Option<&Env>) -> Env {
+        let pending = RefCell::default();
+        let e = Env(Rc::new(Node { scope: RefCell::new(scope), parent: parent.cloned(), pending }));
+        gc::track(Tracked::Env(e.downgrade()));
+        e
+    }
+    /// A root environment that registers each of `modules`, given with the
+    /// names it binds, the first time one of those names is looked up and
+    /// isn't bound. Code never pays for the modules it doesn't use, and a
+    /// name no module binds registers nothing.
+    pub fn lazy(modules: Vec<(Register, &[&str])>) -> Env {
+        let e = Env::new(None);
+        let mut pending = e.0.pending.borrow_mut();
+        for (i, (register, names)) in modules.into_iter().enumerate() {
+            pending.modules.push(Some(register));
+            for name in names {
+                pending.owners.entry(Symbol::intern(name)).or_insert(i);
+            }
+        }
+        drop(pending);
+        e
+    }
+    pub fn new(parent: Option<&Env>) -> Env {
+        Env::with_scope(Scope::default(), parent)
     }
 }
 
@@ -93,101 +510,77 @@ impl<'a, 'b> Env<'a> {
 mod tests {
     use super::*;
     use crate::errors::ErrType;
+    use crate::reader::read;
     use crate::types::*;
 
     #[test]
     fn test_lookup() {
         let mut m = HashMap::new();
-        m.insert("a", Entry::Val(Val::Int(0)));
-        let e = Env {
-            parent: None,
-            scope: Scope(m)
-        };
-        if let Ok(Entry::Val(v)) = e.lookup("a") {
+        m.insert(Symbol::intern("a"), Entry::Val(Val::Int(0)));
+        let e = Env::with_scope(Scope(m), None);
+        if let Ok(Entry::Val(v)) = e.lookup("a").as_deref() {
             assert_eq!(*v, Val::Int(0));
         } else {
             panic!("test_lookup");
-        }
+        };
     }
 
     #[test]
     fn test_lookup_parent() {
         let mut p_m = HashMap::new();
-        p_m.insert("a", Entry::Val(Val::Int(0)));
+        p_m.insert(Symbol::intern("a"), Entry::Val(Val::Int(0)));
         let m = HashMap::new();
-        let parent = Env {
-            parent: None,
-            scope: Scope(p_m),
-        };
-        let e = Env {
-            parent: Some(&parent),
-            scope: Scope(m)
-        };
-        if let Ok(Entry::Val(v)) = e.lookup("a") {
+        let parent = Env::with_scope(Scope(p_m), None);
+        let e = Env::with_scope(Scope(m), Some(&parent));
+        if let Ok(Entry::Val(v)) = e.lookup("a").as_deref() {
             assert_eq!(*v, Val::Int(0));
         } else {
             panic!("test_lookup_parent");
-        }
+        };
     }
 
     #[test]
     fn test_shadow() {
         let mut p_m = HashMap::new();
-        p_m.insert("a", Entry::Val(Val::Int(0)));
+        p_m.insert(Symbol::intern("a"), Entry::Val(Val::Int(0)));
         let mut m = HashMap::new();
-        m.insert("a", Entry::Val(Val::Int(1)));
-        let parent = Env {
-            parent: None,
-            scope: Scope(p_m),
-        };
-        let e = Env {
-            parent: Some(&parent),
-            scope: Scope(m)
-        };
-        if let Ok(Entry::Val(v)) = e.lookup("a") {
+        m.insert(Symbol::intern("a"), Entry::Val(Val::Int(1)));
+        let parent = Env::with_scope(Scope(p_m), None);
+        let e = Env::with_scope(Scope(m), Some(&parent));
+        if let Ok(Entry::Val(v)) = e.lookup("a").as_deref() {
             assert_eq!(*v, Val::Int(1));
         } else {
             panic!("test_shadow");
-        }
+        };
     }
 
     #[test]
     fn test_lookup_error() {
         let mut m = HashMap::new();
-        let a = "a";
-        m.insert(a, Entry::Val(Val::Int(0)));
-        let e = Env {
-            parent: None,
-            scope: Scope(m)
-        };
+        m.insert(Symbol::intern("a"), Entry::Val(Val::Int(0)));
+        let e = Env::with_scope(Scope(m), None);
         let expected_err = ErrType::lookup("b");
         if let Err(err) = e.lookup("b") {
             assert_eq!(err, expected_err);
         } else {
             panic!("test_lookup_error");
-        }
+        };
     }
 
     #[test]
     fn test_lookup_parent_error() {
         let mut p_m = HashMap::new();
-        p_m.insert("a", Entry::Val(Val::Int(0)));
+        p_m.insert(Symbol::intern("a"), Entry::Val(Val::Int(0)));
         let mut m = HashMap::new();
-        m.insert("b", Entry::Val(Val::Int(1)));
-        let parent = Env {
-            parent: None,
-            scope: Scope(p_m),
-        };
-        let e = Env {
-            parent: Some(&parent),
-            scope: Scope(m)
-        };
+        m.insert(Symbol::intern("b"), Entry::Val(Val::Int(1)));
+        let parent = Env::with_scope(Scope(p_m), None);
+        let e = Env::with_scope(Scope(m), Some(&parent));
         let expected_err = ErrType::lookup("c");
         if let Err(err) = e.lookup("c") {
             assert_eq!(err, expected_err);
         } else {
             panic!("test_lookup_parent_error");
-        }
+        };
     }
 
     #[test]
@@ -204,11 +597,10 @@ mod tests {
 
     #[test]
     fn test_func_lookup_varargs() {
-        let f: F = |args| args.into_iter().fold(
-            Ok(Val::Int(0)),
+        let f: F = |args| args.into_iter().try_fold(
+            Val::Int(0),
             |acc, x| {
-                let v1 = acc.unwrap();
-                let v1 = v1.unwrap_int()?;
+                let v1 = acc.unwrap_int()?;
                 let v2 = x.unwrap_int()?;
                 Ok(Val::Int(v1 + v2))
             }
@@ -230,4 +622,98 @@ mod tests {
         f.add_arity(Arity::SomeArgs(2), f2);
         assert!(f.lookup(Arity::SomeArgs(2)).is_some());
     }
+
+    #[test]
+    fn test_func_lookup_most_specific() {
+        let f1: F = |_| Ok(Val::Int(1));
+        let f2: F = |_| Ok(Val::Int(2));
+        let f3: F = |_| Ok(Val::Int(3));
+        let f4: F = |_| Ok(Val::Int(4));
+        let mut f = Func::new(Arity::VarArgs, f1);
+        f.add_arity(Arity::AtLeast(1), f2);
+        f.add_arity(Arity::AtLeast(2), f3);
+        f.add_arity(Arity::Range(1, 3), f4);
+        let call = |a| f.lookup(a).map(|f| f(vec![]).unwrap());
+        assert_eq!(call(Arity::NoArgs), Some(Val::Int(1)));
+        assert_eq!(call(Arity::SomeArgs(1)), Some(Val::Int(4)));
+        assert_eq!(call(Arity::SomeArgs(3)), Some(Val::Int(4)));
+        assert_eq!(call(Arity::SomeArgs(4)), Some(Val::Int(3)));
+    }
+
+    #[test]
+    fn test_func_lookup_range_miss() {
+        let f1: F = |_| Ok(Val::Int(1));
+        let f = Func::new(Arity::Range(1, 2), f1);
+        assert!(f.lookup(Arity::NoArgs).is_none());
+        assert!(f.lookup(Arity::SomeArgs(3)).is_none());
+    }
+
+    fn syms(names: &[&str]) -> Vec<Form> {
+        read(&names.join(" "), "test").unwrap()
+    }
+
+    #[test]
+    fn test_params_arity() -> Result<(), ErrType> {
+        assert_eq!(Params::parse(&[])?.arity(), Arity::NoArgs);
+        assert_eq!(Params::parse(&syms(&["a", "b"]))?.arity(), Arity::SomeArgs(2));
+        assert_eq!(Params::parse(&syms(&["a", "#!optional", "b"]))?.arity(), Arity::Range(1, 2));
+        assert_eq!(Params::parse(&syms(&["a", ".", "r"]))?.arity(), Arity::AtLeast(1));
+        assert_eq!(Params::parse(&syms(&[".", "r"]))?.arity(), Arity::VarArgs);
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_malformed() {
+        assert!(Params::parse(&syms(&["a", "."])).is_err());
+        assert!(Params::parse(&syms(&[".", "r", "s"])).is_err());
+        assert!(Params::parse(&syms(&["1"])).is_err());
+    }
+
+    #[test]
+    fn test_bind() -> Result<(), ErrType> {
+        let params = Params::parse(&syms(&["a", "#!optional", "b", "(c (+ a 1))", ".", "r"]))?;
+        let e = Env::new(None);
+        let missing = e.bind("f", &params, vec![Val::Int(1), Val::Int(2)])?;
+        assert_eq!(e.lookup("a")?.unwrap_val(), Some(&Val::Int(1)));
+        assert_eq!(e.lookup("b")?.unwrap_val(), Some(&Val::Int(2)));
+        let missing: Vec<_> = missing.iter().map(|(s, d)| (*s, d.as_ref().map(Form::to_val))).collect();
+        assert_eq!(missing, vec![(Symbol::intern("c"), Some(syms(&["(+ a 1)"])[0].to_val()))]);
+        assert!(e.lookup("c").is_err());
+        assert_eq!(e.lookup("r")?.unwrap_val(), Some(&Val::List(vec![].into())));
+        let e = Env::new(None);
+        assert!(e.bind("f", &params, (1..6).map(Val::Int).collect())?.is_empty());
+        assert_eq!(e.lookup("r")?.unwrap_val(), Some(&Val::List(vec![Val::Int(4), Val::Int(5)].into())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_pattern() -> Result<(), ErrType> {
+        let params = Params::parse(&syms(&["a", "(b . c)"]))?;
+        assert_eq!(params.arity(), Arity::SomeArgs(2));
+        let e = Env::new(None);
+        e.bind("f", &params, vec![Val::Int(1), Val::List(vec![Val::Int(2), Val::Int(3)].into())])?;
+        assert_eq!(e.lookup("b")?.unwrap_val(), Some(&Val::Int(2)));
+        assert_eq!(e.lookup("c")?.unwrap_val(), Some(&Val::List(vec![Val::Int(3)].into())));
+        let e = Env::new(None);
+        assert_eq!(e.bind("f", &params, vec![Val::Int(1), Val::Int(2)]), Err(ErrType::no_match(Val::Int(2))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_arity_mismatch() -> Result<(), ErrType> {
+        let params = Params::parse(&syms(&["a"]))?;
+        let e = Env::new(None);
+        assert_eq!(e.bind("f", &params, vec![]), Err(ErrType::arity_mismatch("f", &[Arity::SomeArgs(1)], 0)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_func_call_arity_mismatch() {
+        let f1: F = |_| Ok(Val::Int(0));
+        let mut f = Func::new(Arity::NoArgs, f1);
+        f.add_arity(Arity::Range(2, 3), f1);
+        let expected = ErrType::arity_mismatch("f", &[Arity::NoArgs, Arity::Range(2, 3)], 1);
+        assert_eq!(f.call("f", vec![Val::Int(1)]), Err(expected));
+        assert_eq!(f.call("f", vec![]), Ok(Val::Int(0)));
+    }
 }
@@ -1,8 +1,13 @@
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::fmt;
+use std::rc::Rc;
 
-#[derive(Debug, PartialEq, Eq)]
+use super::diagnostic::Span;
+use super::env::Arity;
+use super::types::Val;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TypeError {
     actual: String,
     expected: String,
@@ -17,7 +22,7 @@ impl Display for TypeError {
 
 impl Error for TypeError {}
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct LookupError {
     name: String
 }
@@ -30,7 +35,7 @@ impl Display for LookupError {
 
 impl Error for LookupError {}
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct NotAFunctionError {
     name: String
 }
@@ -43,23 +48,296 @@ impl Display for NotAFunctionError {
 
 impl Error for NotAFunctionError {}
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ArityMismatchError {
     name: String,
+    expected: Vec<Arity>,
+    actual: usize,
 }
 
 impl Display for ArityMismatchError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "Wrong number of args for {}", self.name)
+        write!(f, "Wrong number of args for {}: expected ", self.name)?;
+        for (i, a) in self.expected.iter().enumerate() {
+            if i > 0 {
+                write!(f, " or ")?;
+            }
+            write!(f, "{}", a)?;
+        }
+        write!(f, ", got {}", self.actual)
+    }
+}
+
+impl Error for ArityMismatchError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexError {
+    index: i64,
+    len: usize,
+}
+
+impl Display for IndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Index {} out of range for length {}", self.index, self.len)
+    }
+}
+
+impl Error for IndexError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxError {
+    msg: String,
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Syntax error: {}", self.msg)
+    }
+}
+
+impl Error for SyntaxError {}
+
+/// Raised when integer arithmetic divides by zero or overflows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArithmeticError {
+    op: String,
+    msg: String,
+}
+
+impl Display for ArithmeticError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Arithmetic error: {} in {}", self.msg, self.op)
+    }
+}
+
+impl Error for ArithmeticError {}
+
+/// Raised when a value doesn't have the shape a pattern requires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchError {
+    value: Val,
+}
+
+impl Display for MatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "No pattern matches {}", self.value)
+    }
+}
+
+impl Error for MatchError {}
+
+/// Raised when a file to load, or the file for a module, isn't in any of
+/// the directories searched, or at its absolute path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileNotFoundError {
+    name: String,
+    searched: Vec<String>,
+}
+
+impl Display for FileNotFoundError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.searched.is_empty() {
+            write!(f, "{} not found", self.name)
+        } else {
+            write!(f, "{} not found in {}", self.name, self.searched.join(", "))
+        }
+    }
+}
+
+impl Error for FileNotFoundError {}
+
+/// A resource whose use can be limited; see `eval::Limits`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resource {
+    Fuel,
+    Depth,
+    Cells,
+}
+
+impl Display for Resource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Fuel => write!(f, "fuel"),
+            Resource::Depth => write!(f, "recursion depth"),
+            Resource::Cells => write!(f, "cells"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceLimitError {
+    pub resource: Resource,
+    pub limit: u64,
+}
+
+impl Display for ResourceLimitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Evaluation exceeded its {} limit of {}", self.resource, self.limit)
+    }
+}
+
+impl Error for ResourceLimitError {}
+
+/// Raised when the host interrupts an evaluation through its interrupt handle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Interrupted;
+
+impl Display for Interrupted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Evaluation interrupted")
+    }
+}
+
+impl Error for Interrupted {}
+
+/// Raised when modules import each other; `path` runs from the first module
+/// in the cycle back to itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CycleError {
+    path: Vec<String>,
+}
+
+impl Display for CycleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Import cycle: {}", self.path.join(" -> "))
+    }
+}
+
+impl Error for CycleError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Located {
+    err: Box<ErrType>,
+    span: Span,
+}
+
+impl Display for Located {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.err)
+    }
+}
+
+impl Error for Located {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.err)
+    }
+}
+
+/// An error signalled from Lisp. `error` supplies a message and a list of
+/// irritants as the payload; `raise` supplies an arbitrary payload and no message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserError {
+    msg: Option<String>,
+    payload: Val,
+}
+
+impl Display for UserError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.msg {
+            Some(msg) => {
+                write!(f, "{}", msg)?;
+                if let Val::List(irritants) = &self.payload {
+                    for v in irritants.iter() {
+                        write!(f, " {}", v)?;
+                    }
+                }
+                Ok(())
+            }
+            None => write!(f, "Uncaught exception: {}", self.payload),
+        }
+    }
+}
+
+impl Error for UserError {}
+
+/// A procedure call in progress: the procedure's name and the call site.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub name: String,
+    pub span: Span,
+}
+
+/// The call stack at the point an error was raised, innermost call first.
+/// Deep stacks keep only their innermost and outermost frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Backtrace {
+    frames: Vec<Frame>,
+    omitted: usize,
+}
+
+impl Backtrace {
+    const KEEP: usize = 10;
+
+    pub fn new(stack: &[Frame]) -> Backtrace {
+        let omitted = stack.len().saturating_sub(2 * Backtrace::KEEP);
+        let frames = if omitted > 0 {
+            let n = stack.len();
+            stack[n - Backtrace::KEEP..].iter().rev()
+                .chain(stack[..Backtrace::KEEP].iter().rev())
+                .cloned()
+                .collect()
+        } else {
+            stack.iter().rev().cloned().collect()
+        };
+        Backtrace { frames, omitted }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Display for Backtrace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Backtrace (innermost first):")?;
+        for (i, frame) in self.frames.iter().enumerate() {
+            if self.omitted > 0 && i == Backtrace::KEEP {
+                write!(f, "\n  ... {} frames omitted ...", self.omitted)?;
+            }
+            write!(f, "\n  {} at {}", frame.name, frame.span)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Traced {
+    err: Box<ErrType>,
+    backtrace: Backtrace,
+}
+
+impl Display for Traced {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n{}", self.err, self.backtrace)
+    }
+}
+
+impl Error for Traced {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.err)
+    }
+}
+
+/// An error from reading or evaluating code. Only `Located` and `Traced`
+/// have a `source`: the error they add a span or backtrace to.
+///
+/// Errors can carry Lisp values, which are reference counted, so `ErrType`
+/// is neither `Send` nor `Sync` and doesn't convert into
+/// `Box<dyn Error + Send + Sync>`. Use its message, e.g. through
+/// `to_string()`, to report it from another thread.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ErrType {
     TypeError(TypeError),
     LookupError(LookupError),
     NotAFunctionError(NotAFunctionError),
     ArityMismatchError(ArityMismatchError),
+    IndexError(IndexError),
+    SyntaxError(SyntaxError),
+    MatchError(MatchError),
+    ArithmeticError(ArithmeticError),
+    CycleError(CycleError),
+    FileNotFoundError(FileNotFoundError),
+    ResourceLimit(ResourceLimitError),
+    Interrupted(Interrupted),
+    Located(Located),
+    Traced(Traced),
+    User(UserError),
 }
 
 impl Display for ErrType {
@@ -69,6 +347,39 @@ impl Display for ErrType {
             ErrType::LookupError(e) => write!(f, "{}", e),
             ErrType::NotAFunctionError(e) => write!(f, "{}", e),
             ErrType::ArityMismatchError(e) => write!(f, "{}", e),
+            ErrType::IndexError(e) => write!(f, "{}", e),
+            ErrType::SyntaxError(e) => write!(f, "{}", e),
+            ErrType::MatchError(e) => write!(f, "{}", e),
+            ErrType::ArithmeticError(e) => write!(f, "{}", e),
+            ErrType::CycleError(e) => write!(f, "{}", e),
+            ErrType::FileNotFoundError(e) => write!(f, "{}", e),
+            ErrType::ResourceLimit(e) => write!(f, "{}", e),
+            ErrType::Interrupted(e) => write!(f, "{}", e),
+            ErrType::Located(e) => write!(f, "{}", e),
+            ErrType::Traced(e) => write!(f, "{}", e),
+            ErrType::User(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for ErrType {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ErrType::TypeError(e) => e.source(),
+            ErrType::LookupError(e) => e.source(),
+            ErrType::NotAFunctionError(e) => e.source(),
+            ErrType::ArityMismatchError(e) => e.source(),
+            ErrType::IndexError(e) => e.source(),
+            ErrType::SyntaxError(e) => e.source(),
+            ErrType::MatchError(e) => e.source(),
+            ErrType::ArithmeticError(e) => e.source(),
+            ErrType::CycleError(e) => e.source(),
+            ErrType::FileNotFoundError(e) => e.source(),
+            ErrType::ResourceLimit(e) => e.source(),
+            ErrType::Interrupted(e) => e.source(),
+            ErrType::Located(e) => e.source(),
+            ErrType::Traced(e) => e.source(),
+            ErrType::User(e) => e.source(),
         }
     }
 }
@@ -83,7 +394,181 @@ impl ErrType {
     pub fn not_a_function(name: &str) -> ErrType {
         ErrType::NotAFunctionError(NotAFunctionError { name: String::from(name) })
     }
-    pub fn arity_mismatch(name: &str) -> ErrType {
-        ErrType::ArityMismatchError(ArityMismatchError { name: String::from(name) })
+    pub fn arity_mismatch(name: &str, expected: &[Arity], actual: usize) -> ErrType {
+        let mut expected = expected.to_vec();
+        expected.sort_by_key(|a| a.bounds());
+        ErrType::ArityMismatchError(ArityMismatchError { name: String::from(name), expected, actual })
+    }
+    pub fn index(index: i64, len: usize) -> ErrType {
+        ErrType::IndexError(IndexError { index, len })
+    }
+    pub fn syntax(msg: &str) -> ErrType {
+        ErrType::SyntaxError(SyntaxError { msg: String::from(msg) })
+    }
+    pub fn no_match(value: Val) -> ErrType {
+        ErrType::MatchError(MatchError { value })
+    }
+    pub fn arithmetic(op: &str, msg: &str) -> ErrType {
+        ErrType::ArithmeticError(ArithmeticError { op: String::from(op), msg: String::from(msg) })
+    }
+    pub fn cycle(path: &[&str]) -> ErrType {
+        ErrType::CycleError(CycleError { path: path.iter().map(|s| String::from(*s)).collect() })
+    }
+    pub fn file_not_found(name: &str, searched: &[&str]) -> ErrType {
+        let searched = searched.iter().map(|s| String::from(*s)).collect();
+        ErrType::FileNotFoundError(FileNotFoundError { name: String::from(name), searched })
+    }
+    pub fn resource_limit(resource: Resource, limit: u64) -> ErrType {
+        ErrType::ResourceLimit(ResourceLimitError { resource, limit })
+    }
+    pub fn interrupted() -> ErrType {
+        ErrType::Interrupted(Interrupted)
+    }
+    pub fn user(msg: &str, irritants: Vec<Val>) -> ErrType {
+        ErrType::User(UserError { msg: Some(String::from(msg)), payload: Val::List(irritants.into()) })
+    }
+    pub fn raise(payload: Val) -> ErrType {
+        ErrType::User(UserError { msg: None, payload })
+    }
+    /// Attaches `span` to this error, unless it already has a more precise one.
+    pub fn at(self, span: Span) -> ErrType {
+        if self.span().is_some() {
+            self
+        } else {
+            ErrType::Located(Located { err: Box::new(self), span })
+        }
+    }
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            ErrType::Located(e) => Some(&e.span),
+            ErrType::Traced(e) => e.err.span(),
+            _ => None,
+        }
+    }
+    /// Attaches a backtrace of `stack` to this error, unless it already has one.
+    pub fn traced(self, stack: &[Frame]) -> ErrType {
+        if self.backtrace().is_some() {
+            self
+        } else {
+            ErrType::Traced(Traced { err: Box::new(self), backtrace: Backtrace::new(stack) })
+        }
+    }
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self {
+            ErrType::Traced(e) => Some(&e.backtrace),
+            _ => None,
+        }
+    }
+    /// This error without its backtrace.
+    pub fn untraced(&self) -> &ErrType {
+        match self {
+            ErrType::Traced(e) => &e.err,
+            e => e,
+        }
+    }
+    /// Whether this error must end the evaluation, rather than being handled
+    /// by `guard` or `handler-bind`. It ends it at once, without calling the
+    /// after thunks of `dynamic-wind`s.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self.root(), ErrType::ResourceLimit(_) | ErrType::Interrupted(_))
+    }
+    /// This error without its backtrace or span.
+    pub fn root(&self) -> &ErrType {
+        match self {
+            ErrType::Traced(e) => e.err.root(),
+            ErrType::Located(e) => e.err.root(),
+            e => e,
+        }
+    }
+    /// The value a Lisp handler sees for this error: the payload given to
+    /// `raise`, or otherwise the error itself as an error object.
+    pub fn to_val(&self) -> Val {
+        match self.root() {
+            ErrType::User(UserError { msg: None, payload }) => payload.clone(),
+            _ => Val::Error(Rc::new(self.clone())),
+        }
+    }
+    /// The message of an error object, without any irritants.
+    pub fn message(&self) -> String {
+        match self.root() {
+            ErrType::User(UserError { msg: Some(msg), .. }) => msg.clone(),
+            e => e.to_string(),
+        }
+    }
+    pub fn irritants(&self) -> Val {
+        match self.root() {
+            ErrType::User(UserError { msg: Some(_), payload }) => payload.clone(),
+            _ => Val::List(Vec::new().into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_arity_mismatch() {
+        let e = ErrType::arity_mismatch("f", &[Arity::AtLeast(3), Arity::SomeArgs(1)], 2);
+        assert_eq!(e.to_string(), "Wrong number of args for f: expected 1 or at least 3, got 2");
+    }
+
+    fn frames(n: usize) -> Vec<Frame> {
+        (0..n).map(|i| {
+            let span = Span { file: "test".into(), line: i + 1, col: 1, end_line: i + 1, end_col: 2 };
+            Frame { name: format!("f{}", i), span }
+        }).collect()
+    }
+
+    #[test]
+    fn test_display_backtrace() {
+        let e = ErrType::lookup("a").traced(&frames(2));
+        let expected = "a not found\nBacktrace (innermost first):\n  f1 at test:2:1\n  f0 at test:1:1";
+        assert_eq!(e.to_string(), expected);
+    }
+
+    #[test]
+    fn test_backtrace_truncated() {
+        let bt = Backtrace::new(&frames(100));
+        assert_eq!(bt.frames.len(), 20);
+        assert_eq!(bt.omitted, 80);
+        assert_eq!(bt.frames[0].name, "f99");
+        assert_eq!(bt.frames[19].name, "f0");
+        assert!(bt.to_string().contains("\n  f90 at test:91:1\n  ... 80 frames omitted ...\n  f9 at"));
+    }
+
+    #[test]
+    fn test_display_user() {
+        let e = ErrType::user("bad thing:", vec![Val::Int(1), Val::Bool(true)]);
+        assert_eq!(e.to_string(), "bad thing: 1 true");
+        assert_eq!(ErrType::raise(Val::Int(1)).to_string(), "Uncaught exception: 1");
+    }
+
+    #[test]
+    fn test_to_val() {
+        assert_eq!(ErrType::raise(Val::Int(1)).traced(&frames(1)).to_val(), Val::Int(1));
+        let e = ErrType::lookup("a");
+        assert_eq!(e.to_val(), Val::Error(Rc::new(e)));
+    }
+
+    #[test]
+    fn test_source() {
+        let e = ErrType::lookup("a");
+        assert!(e.source().is_none());
+        let span = Span { file: "test".into(), line: 1, col: 1, end_line: 1, end_col: 2 };
+        let e = e.at(span).traced(&frames(1));
+        let located = e.source().unwrap();
+        assert_eq!(located.to_string(), "test:1:1: a not found");
+        let root = located.source().unwrap();
+        assert_eq!(root.to_string(), "a not found");
+        assert!(root.source().is_none());
+    }
+
+    #[test]
+    fn test_into_box_dyn_error() {
+        fn host() -> Result<(), Box<dyn Error>> {
+            Err(ErrType::not_a_function("a"))?
+        }
+        assert_eq!(host().unwrap_err().to_string(), "a is not a function");
     }
 }
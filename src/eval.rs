@@ -1,7 +1,2031 @@
-use crate::errors::ErrType;
+use std::convert::TryFrom;
+use std::fmt;
+#[cfg(feature = "io")]
+use std::fs;
+#[cfg(feature = "io")]
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::diagnostic::Span;
+use crate::errors::{ErrType, Frame, Resource};
 use crate::env::*;
+#[cfg(feature = "io")]
+use crate::module;
+use crate::module::{ImportSet, Module, Modules};
+use crate::pattern::{Bindings, Matcher, Pattern, PatternCache, Step};
+use crate::reader::{self, Form, FormKind, Forms};
+use crate::stdlib::map;
+use crate::symbol::Symbol;
 use crate::types::*;
 
+/// A `handler-bind` clause: when `pred` holds for a condition, call `handler`
+/// with it.
+#[derive(Clone)]
+struct Handler {
+    pred: Val,
+    handler: Val,
+}
+
+/// A `restart-case` clause: the restart's name, its lambda list and its body.
+#[derive(Clone)]
+struct Restart {
+    name: Symbol,
+    params: Params,
+    body: Forms,
+}
+
+/// Bounds on what an evaluation may use, each unbounded if `None`. Usage is
+/// counted from the start of each `eval_str`. The default bounds only the
+/// depth, to `DEFAULT_DEPTH`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The number of forms evaluated.
+    pub fuel: Option<u64>,
+    /// The number of frames on the evaluator's stack, which grows with nested
+    /// calls. Without a limit, runaway recursion uses memory until the host
+    /// runs out.
+    pub depth: Option<usize>,
+    /// The number of elements added to collections, as counted by `cells`.
+    pub cells: Option<u64>,
+}
+
+impl Limits {
+    /// The depth evaluation is limited to by default: tens of thousands of
+    /// nested calls, in a few tens of megabytes of frames.
+    pub const DEFAULT_DEPTH: usize = 100_000;
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { fuel: None, depth: Some(Limits::DEFAULT_DEPTH), cells: None }
+    }
+}
+
+/// The number of elements `v` holds directly.
+fn cells(v: &Val) -> u64 {
+    let n = match v {
+        Val::List(l) => l.len(),
+        Val::Vector(v) => v.borrow().len(),
+        Val::Map(m) => m.len(),
+        Val::PVector(v) => v.len(),
+        Val::Set(s) => s.len(),
+        Val::Record(r) => r.fields.borrow().len(),
+        _ => 0,
+    };
+    n as u64
+}
+
+/// The rest of a computation, as the frames the `call/cc` form that captured
+/// it would return through. Calling it replaces the evaluator's frames with a
+/// copy of these, so it can be called any number of times, including after
+/// `call/cc` has returned.
+///
+/// A continuation `shift` captures is delimited: its frames are those above
+/// the innermost `reset`, which started at depth `origin`. Calling it pushes
+/// a copy of them instead, so the call returns once they do.
+pub struct Continuation {
+    frames: Vec<Kont>,
+    origin: Option<usize>,
+}
+
+impl fmt::Debug for Continuation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#<continuation>")
+    }
+}
+
+/// A frame of the evaluator's stack, saying what to do with the value of the
+/// form evaluated last. Frames own the forms and environments they need, so a
+/// continuation can keep them after they've been popped.
+#[derive(Clone)]
+enum Kont {
+    /// The rest of a body, whose last value is returned.
+    Body(Forms, Env),
+    /// Evaluates `forms` in turn, collecting their values in `vals`.
+    Gather { forms: Forms, vals: Vec<Val>, env: Env, then: Gathered },
+    /// A procedure call in progress. Errors leaving it get its span and a
+    /// backtrace of the calls in progress.
+    Call(Frame),
+    Define(Symbol, Env),
+    /// The value of the first of `bindings`, to destructure into `vars`.
+    Let { bindings: Forms, pattern: Pattern, vars: Bindings, env: Env, body: Forms },
+    If { then: Form, otherwise: Option<Form>, env: Env },
+    And(Forms, Env),
+    Or(Forms, Env),
+    When { expected: bool, body: Forms, env: Env },
+    /// The test of the first of `clauses`; see `clauses`.
+    Clause { clauses: Forms, env: Env, span: Span, otherwise: Option<ErrType> },
+    /// A `guard` body, whose errors are bound to `var` and tried against
+    /// `clauses`.
+    Guard { var: Symbol, clauses: Forms, env: Env, span: Span },
+    /// The handlers a `handler-bind` makes active while its body runs.
+    Handlers(Rc<[Handler]>),
+    /// The restarts a `restart-case` makes available while its expression
+    /// runs, and the environment their bodies are evaluated in a child of.
+    Restarts(Rc<[Restart]>, Env),
+    /// An error offered to `handlers`, innermost first, each with the depth
+    /// of the frame establishing it. While the handlers run, this frame is
+    /// also the target of the `use-value` restart.
+    Signal { err: ErrType, cond: Val, handlers: Rc<[(Handler, usize)]>, span: Span },
+    /// The predicate, or if `pred` is false the handler, of the `i`th handler
+    /// of the signal below. Handlers established at or above `depth` are
+    /// disabled meanwhile.
+    Handling { i: usize, depth: usize, pred: bool },
+    /// Assocs the value into `coll` at `k`, for `update`.
+    Assoc { coll: Val, k: Val, span: Span },
+    /// Calls the procedure received with `args`.
+    Apply { args: Args, span: Span },
+    /// The value of a `match` expression, to try against `clauses`.
+    Match { clauses: Forms, env: Env, span: Span },
+    /// A `(? pred)` predicate called by `matcher`, matching `value` against
+    /// the first of `clauses`.
+    MatchPred { matcher: Matcher, value: Val, clauses: Forms, env: Env, span: Span },
+    /// The default of the `next`th of the `missing` optional parameters.
+    Defaults { missing: Rc<[(Symbol, Option<Form>)]>, next: usize, local: Env, body: Forms },
+    /// The before thunk of a `dynamic-wind`, whose `thunk` runs next.
+    Before { before: Val, thunk: Val, after: Val, span: Span },
+    /// The thunk of a `dynamic-wind`, `id` identifying this activation of it.
+    /// Leaving it, however that happens, calls `after`; re-entering it
+    /// through a continuation calls `before`.
+    Wind { id: usize, before: Val, after: Val, span: Span },
+    /// Returns this value rather than the one received.
+    Value(Val),
+    /// Raises this error rather than returning the value received.
+    Reraise(ErrType),
+    /// Carries on unwinding once an after thunk returns.
+    Unwind { depth: usize, then: Then },
+    /// Carries on re-entering `k`, whose frames go above depth `base`, once a
+    /// before thunk returns.
+    Rewind { k: Rc<Continuation>, v: Val, base: usize, next: usize },
+    /// The delimiter of a `reset`, up to which `shift` captures the stack.
+    Reset,
+    /// The body of a `module` form.
+    Module { name: Symbol, exports: Vec<Symbol>, env: Env, span: Span },
+    /// The file defining module `name`, loaded by `import`.
+    #[cfg(feature = "io")]
+    LoadModule { name: Symbol, path: PathBuf, span: Span },
+    /// The import sets of an `import`, the first waiting for its module to
+    /// load.
+    #[cfg(feature = "io")]
+    Import { sets: Forms, env: Env, span: Span },
+    /// The environment top-level forms are evaluated in, and `load` evaluates
+    /// files in, for the frames above: the one `eval_str` was given, or a
+    /// module file's while `import` loads it.
+    #[cfg(feature = "io")]
+    TopLevel(Env),
+}
+
+/// What a `Gather` frame does with the values it collects.
+#[derive(Clone)]
+enum Gathered {
+    /// Calls the procedure bound to the name with them.
+    Call(Symbol, Span),
+    /// Calls the first with the rest.
+    CallValue(Span),
+    Vector,
+    Map,
+    Set,
+    Update(Span),
+    CallCc(Span),
+    DynamicWind(Span),
+    /// Makes handlers of them, in pairs, active for the body.
+    HandlerBind(Forms),
+}
+
+/// Where unwinding the stack leads.
+#[derive(Clone)]
+enum Then {
+    /// To the restart of that name of the frame unwound to, with the arguments.
+    Restart(Symbol, Args),
+    /// Into continuation `k` with a value, entering its `dynamic-wind`s from
+    /// the `n`th on.
+    Enter(Rc<Continuation>, Val, usize),
+    /// To the body of a `shift`, with its variable bound to continuation `k`.
+    Shift { k: Rc<Continuation>, var: Symbol, body: Forms, env: Env },
+}
+
+/// The evaluator's next step.
+enum Ctl {
+    Eval(Form, Env),
+    Return(Val),
+    Raise(ErrType),
+}
+
+/// The depth and id of each `dynamic-wind` in `frames`, outermost first.
+fn winds(frames: &[Kont]) -> Vec<(usize, usize)> {
+    frames.iter()
+        .enumerate()
+        .filter_map(|(depth, k)| match k {
+            Kont::Wind { id, .. } => Some((depth, *id)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Evaluates forms on a stack of frames of its own rather than the host's, so
+/// continuations can capture and resume it, and deep recursion can't overflow
+/// the host's stack. The frames also establish the active handlers, restarts
+/// and `dynamic-wind`s, and record the calls in progress for backtraces. The
+/// evaluator also keeps the modules defined so far.
+#[derive(Default)]
+pub struct Evaluator {
+    konts: Vec<Kont>,
+    next_id: usize,
+    modules: Modules,
+    patterns: PatternCache,
+    /// The directories `load` and `import` search for files.
+    #[cfg(feature = "io")]
+    search_path: Vec<PathBuf>,
+    /// Whether `load` and `import` are kept from reading files.
+    #[cfg(feature = "io")]
+    sandboxed: bool,
+    limits: Limits,
+    steps: u64,
+    cells: u64,
+    interrupt: Arc<AtomicBool>,
+}
+
+impl Evaluator {
+    /// An evaluator with the default limits, whose only one is a depth of
+    /// `Limits::DEFAULT_DEPTH`, so runaway recursion raises an error rather
+    /// than exhausting memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An evaluator for untrusted code: `load` isn't available, `import` only
+    /// finds modules already defined, and evaluation is bounded by `limits`.
+    /// Pair it with an environment from `stdlib::Builder::sandbox`.
+    pub fn sandboxed(limits: Limits) -> Self {
+        Evaluator {
+            #[cfg(feature = "io")]
+            sandboxed: true,
+            limits,
+            ..Self::default()
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// A flag another thread, or a signal handler, can set to stop the
+    /// current evaluation with an `Interrupted` error. The evaluator clears
+    /// it on stopping, so the next evaluation runs normally.
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupt)
+    }
+
+    /// Sets the directories `load` and `import` search, in order. An empty
+    /// search path means the working directory.
+    #[cfg(feature = "io")]
+    pub fn set_search_path(&mut self, dirs: Vec<PathBuf>) {
+        self.search_path = dirs;
+    }
+
+    /// Reads and evaluates every form in `source`, returning the last value.
+    pub fn eval_str(&mut self, source: &str, file: &str, env: &mut Env) -> Res {
+        self.steps = 0;
+        self.cells = 0;
+        let forms = Forms::from(reader::read(source, file)?);
+        #[cfg(feature = "io")]
+        self.konts.push(Kont::TopLevel(env.clone()));
+        let start = self.body(forms, env.clone());
+        self.run(start)
+    }
+
+    /// Steps until the stack is empty, returning the last value, or the error
+    /// no frame handled. A fatal error empties the stack at once, without
+    /// calling the after thunks of the `dynamic-wind`s it leaves: they could
+    /// otherwise run on past the limit that was hit, or ignore the interrupt.
+    fn run(&mut self, mut ctl: Ctl) -> Res {
+        loop {
+            ctl = match ctl {
+                Ctl::Eval(form, env) => {
+                    let res = self.step().and_then(|_| self.eval_form(&form, env));
+                    res.unwrap_or_else(|e| self.signal(e, &form.span))
+                }
+                Ctl::Return(v) => match self.konts.pop() {
+                    Some(k) => self.resume(k, v).unwrap_or_else(|e| match e.span().cloned() {
+                        Some(span) => self.signal(e, &span),
+                        None => Ctl::Raise(e),
+                    }),
+                    None => return Ok(v),
+                },
+                Ctl::Raise(e) if e.is_fatal() => {
+                    let e = e.traced(&self.backtrace());
+                    self.konts.clear();
+                    return Err(e);
+                }
+                Ctl::Raise(e) => match self.konts.pop() {
+                    Some(k) => self.unwind_error(k, e),
+                    None => return Err(e),
+                },
+            }
+        }
+    }
+
+    /// Counts a form about to be evaluated against the fuel and depth limits,
+    /// and checks for an interrupt.
+    fn step(&mut self) -> Result<(), ErrType> {
+        if self.interrupt.load(Ordering::Relaxed) {
+            self.interrupt.store(false, Ordering::Relaxed);
+            return Err(ErrType::interrupted());
+        }
+        self.steps += 1;
+        match self.limits {
+            Limits { fuel: Some(fuel), .. } if self.steps > fuel => {
+                Err(ErrType::resource_limit(Resource::Fuel, fuel))
+            }
+            Limits { depth: Some(depth), .. } if self.konts.len() >= depth => {
+                Err(ErrType::resource_limit(Resource::Depth, depth as u64))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts `n` new collection elements against the cells limit.
+    fn allocate(&mut self, n: u64) -> Result<(), ErrType> {
+        self.cells += n;
+        match self.limits.cells {
+            Some(limit) if self.cells > limit => Err(ErrType::resource_limit(Resource::Cells, limit)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The calls in progress, outermost first.
+    fn backtrace(&self) -> Vec<Frame> {
+        self.konts.iter()
+            .filter_map(|k| match k {
+                Kont::Call(frame) => Some(frame.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn new_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn eval_form(&mut self, form: &Form, env: Env) -> Result<Ctl, ErrType> {
+        let items = match &form.kind {
+            FormKind::Atom(Val::Symbol(s)) => return self.eval_symbol(*s, &env).map(Ctl::Return),
+            FormKind::Atom(v) => return Ok(Ctl::Return(v.clone())),
+            FormKind::Vector(items) => {
+                self.allocate(items.len() as u64)?;
+                return self.gather(Forms::new(items, 0), env, Gathered::Vector);
+            }
+            FormKind::Map(items) => {
+                self.allocate(items.len() as u64 / 2)?;
+                return self.gather(Forms::new(items, 0), env, Gathered::Map);
+            }
+            FormKind::Set(items) => {
+                self.allocate(items.len() as u64)?;
+                return self.gather(Forms::new(items, 0), env, Gathered::Set);
+            }
+            FormKind::List(items) => items,
+        };
+        let name = match items.first() {
+            None => return Ok(Ctl::Return(Val::List(List::new()))),
+            Some(Form { kind: FormKind::Atom(Val::Symbol(s)), .. }) => *s,
+            Some(_) => return self.gather(Forms::new(items, 0), env, Gathered::CallValue(form.span.clone())),
+        };
+        let rest = Forms::new(items, 1);
+        let span = &form.span;
+        match name.as_str() {
+            "quote" => {
+                let v = Evaluator::eval_quote(&rest)?;
+                self.allocate(cells(&v))?;
+                Ok(Ctl::Return(v))
+            }
+            "define" => self.eval_define(rest, env),
+            "let" => self.eval_let(rest, env),
+            "lambda" => Evaluator::eval_lambda(&rest, &env).map(Ctl::Return),
+            "define-record-type" => Evaluator::eval_define_record_type(&rest, &env).map(Ctl::Return),
+            "if" => self.eval_if(rest, env),
+            "and" => Ok(self.and(rest, env)),
+            "or" => Ok(self.or(rest, env)),
+            "when" => self.eval_when(rest, true, env),
+            "unless" => self.eval_when(rest, false, env),
+            "cond" => self.clauses(rest, env, span.clone(), None),
+            "guard" => self.eval_guard(rest, span, env),
+            "handler-bind" => self.eval_handler_bind(rest, env),
+            "restart-case" => self.eval_restart_case(rest, env),
+            "call/cc" | "call-with-current-continuation" => self.eval_call_cc(rest, span, env),
+            "dynamic-wind" => self.eval_dynamic_wind(rest, span, env),
+            "reset" => {
+                self.konts.push(Kont::Reset);
+                Ok(self.body(rest, env))
+            }
+            "shift" => self.eval_shift(rest, env),
+            "update" => self.eval_update(rest, span, env),
+            "match" => self.eval_match(rest, span, env),
+            "module" => self.eval_module(rest, span, env),
+            "import" => self.import(rest, span, env),
+            _ => self.gather(rest, env, Gathered::Call(name, span.clone())),
+        }
+    }
+
+    /// Passes `v`, the value of the form evaluated last, to frame `k`, which
+    /// has been popped off the stack.
+    fn resume(&mut self, k: Kont, v: Val) -> Result<Ctl, ErrType> {
+        match k {
+            Kont::Body(body, env) => Ok(self.body(body, env)),
+            Kont::Gather { forms, mut vals, env, then } => {
+                vals.push(v);
+                self.gathered(forms, vals, env, then)
+            }
+            Kont::Define(name, env) => {
+                env.register_symbol(name, v);
+                Ok(Ctl::Return(Val::Nil))
+            }
+            Kont::Let { bindings, pattern, mut vars, env, body } => {
+                vars.extend(pattern.destructure(&v).map_err(|e| e.at(bindings[0].span.clone()))?);
+                self.let_bindings(bindings.rest(), vars, env, body)
+            }
+            Kont::If { then, otherwise, env } => Ok(if v.is_truthy() {
+                Ctl::Eval(then, env)
+            } else {
+                otherwise.map_or(Ctl::Return(Val::Nil), |f| Ctl::Eval(f, env))
+            }),
+            Kont::And(rest, env) if v.is_truthy() => Ok(self.and(rest, env)),
+            Kont::Or(rest, env) if !v.is_truthy() => Ok(self.or(rest, env)),
+            Kont::When { expected, body, env } if v.is_truthy() == expected => Ok(self.body(body, env)),
+            Kont::When { .. } => Ok(Ctl::Return(Val::Nil)),
+            Kont::Clause { clauses, env, span, otherwise } => self.clause(v, clauses, env, span, otherwise),
+            Kont::Handling { i, pred: true, .. } if v.is_truthy() => self.offer(i, false),
+            Kont::Handling { i, .. } => self.offer(i + 1, true),
+            Kont::Assoc { coll, k, span } => map::assoc(vec![coll, k, v]).map(Ctl::Return).map_err(|e| e.at(span)),
+            Kont::Apply { args, span } => self.apply_value(&v, args, &span).map_err(|e| e.at(span)),
+            Kont::Match { clauses, env, span } => self.try_match(v, clauses, None, env, span),
+            Kont::MatchPred { mut matcher, value, clauses, env, span } => {
+                matcher.answer(v.is_truthy());
+                self.try_match(value, clauses, Some(matcher), env, span)
+            }
+            Kont::Defaults { missing, next, local, body } => {
+                local.register_symbol(missing[next].0, v);
+                Ok(self.defaults(missing, next + 1, local, body))
+            }
+            Kont::Before { before, thunk, after, span } => {
+                let id = self.new_id();
+                self.konts.push(Kont::Wind { id, before, after, span: span.clone() });
+                self.apply_value(&thunk, vec![], &span).map_err(|e| e.at(span))
+            }
+            Kont::Wind { after, span, .. } => {
+                self.konts.push(Kont::Value(v));
+                self.apply_value(&after, vec![], &span).map_err(|e| e.at(span))
+            }
+            Kont::Value(v) => Ok(Ctl::Return(v)),
+            Kont::Reraise(e) => Ok(Ctl::Raise(e)),
+            Kont::Unwind { depth, then } => self.unwind(depth, then),
+            Kont::Rewind { k, v, base, next } => self.rewind(k, v, base, next),
+            Kont::Module { name, exports, env, span } => {
+                if let Some(missing) = exports.iter().find(|e| !env.defines(**e)) {
+                    return Err(ErrType::lookup(missing.as_str()).at(span));
+                }
+                self.modules.insert(name, Module { env, exports });
+                Ok(Ctl::Return(Val::Nil))
+            }
+            #[cfg(feature = "io")]
+            Kont::LoadModule { name, path, span } => {
+                if !self.modules.contains_key(&name) {
+                    let irritants = vec![Val::string(&path.to_string_lossy()), Val::Symbol(name)];
+                    return Err(ErrType::user("file doesn't define its module", irritants).at(span));
+                }
+                Ok(Ctl::Return(Val::Nil))
+            }
+            #[cfg(feature = "io")]
+            Kont::Import { sets, env, span } => {
+                let at = span.clone();
+                self.import(sets, &span, env).map_err(|e| e.at(at))
+            }
+            Kont::Call(_) | Kont::Guard { .. } | Kont::Handlers(_) | Kont::Restarts(..)
+            | Kont::Signal { .. } | Kont::And(..) | Kont::Or(..) | Kont::Reset => Ok(Ctl::Return(v)),
+            #[cfg(feature = "io")]
+            Kont::TopLevel(_) => Ok(Ctl::Return(v)),
+        }
+    }
+
+    /// Passes error `e` to frame `k`, which has been popped off the stack.
+    fn unwind_error(&mut self, k: Kont, e: ErrType) -> Ctl {
+        match k {
+            Kont::Call(frame) => {
+                let e = e.at(frame.span.clone());
+                if e.backtrace().is_some() {
+                    return Ctl::Raise(e);
+                }
+                let mut stack = self.backtrace();
+                stack.push(frame);
+                Ctl::Raise(e.traced(&stack))
+            }
+            Kont::Guard { var, clauses, env, span } => {
+                let local = Env::new(Some(&env));
+                local.register_symbol(var, e.to_val());
+                self.clauses(clauses, local, span, Some(e)).unwrap_or_else(Ctl::Raise)
+            }
+            Kont::Wind { after, span, .. } => {
+                self.konts.push(Kont::Reraise(e));
+                self.apply_value(&after, vec![], &span).unwrap_or_else(Ctl::Raise)
+            }
+            _ => Ctl::Raise(e),
+        }
+    }
+
+    /// Evaluates `forms` in turn, then carries on with their values as `then`
+    /// says.
+    fn gather(&mut self, forms: Forms, env: Env, then: Gathered) -> Result<Ctl, ErrType> {
+        let vals = Vec::with_capacity(forms.len());
+        self.gathered(forms, vals, env, then)
+    }
+
+    fn gathered(&mut self, forms: Forms, vals: Vec<Val>, env: Env, then: Gathered) -> Result<Ctl, ErrType> {
+        match forms.first() {
+            Some(form) => {
+                let form = form.clone();
+                self.konts.push(Kont::Gather { forms: forms.rest(), vals, env: env.clone(), then });
+                Ok(Ctl::Eval(form, env))
+            }
+            None => self.finish(vals, env, then),
+        }
+    }
+
+    fn finish(&mut self, mut vals: Vec<Val>, env: Env, then: Gathered) -> Result<Ctl, ErrType> {
+        match then {
+            Gathered::Call(name, span) => self.apply_named(name, vals, &span, &env).map_err(|e| e.at(span)),
+            Gathered::CallValue(span) => {
+                let f = vals.remove(0);
+                self.apply_value(&f, vals, &span).map_err(|e| e.at(span))
+            }
+            Gathered::Vector => Ok(Ctl::Return(Val::vector(vals))),
+            Gathered::Map => {
+                Ok(Ctl::Return(Val::Map(vals.chunks(2).map(|kv| (kv[0].clone(), kv[1].clone())).collect())))
+            }
+            Gathered::Set => Ok(Ctl::Return(Val::Set(vals.into_iter().collect()))),
+            Gathered::Update(span) => {
+                let extra = vals.split_off(3);
+                let (coll, k, f) = match <[Val; 3]>::try_from(vals) {
+                    Ok([coll, k, f]) => (coll, k, f),
+                    Err(_) => unreachable!("update is checked to take at least three arguments"),
+                };
+                let mut args = vec![map::get(vec![coll.clone(), k.clone()]).map_err(|e| e.at(span.clone()))?];
+                args.extend(extra);
+                self.konts.push(Kont::Assoc { coll, k, span: span.clone() });
+                self.apply_value(&f, args, &span)
+            }
+            Gathered::CallCc(span) => {
+                let k = Val::Continuation(Rc::new(Continuation { frames: self.konts.clone(), origin: None }));
+                self.apply_value(&vals[0], vec![k], &span).map_err(|e| e.at(span))
+            }
+            Gathered::DynamicWind(span) => {
+                let (before, thunk, after) = match <[Val; 3]>::try_from(vals) {
+                    Ok([before, thunk, after]) => (before, thunk, after),
+                    Err(_) => unreachable!("dynamic-wind is checked to take three arguments"),
+                };
+                self.konts.push(Kont::Before { before: before.clone(), thunk, after, span: span.clone() });
+                self.apply_value(&before, vec![], &span)
+            }
+            Gathered::HandlerBind(body) => {
+                let handlers = vals.chunks(2).map(|h| Handler { pred: h[0].clone(), handler: h[1].clone() });
+                self.konts.push(Kont::Handlers(handlers.collect()));
+                Ok(self.body(body, env))
+            }
+        }
+    }
+
+    /// Evaluates `body` in `env`, returning its last value, or nil if it's
+    /// empty.
+    fn body(&mut self, body: Forms, env: Env) -> Ctl {
+        match &body[..] {
+            [] => Ctl::Return(Val::Nil),
+            [last] => Ctl::Eval(last.clone(), env),
+            [first, ..] => {
+                let first = first.clone();
+                self.konts.push(Kont::Body(body.rest(), env.clone()));
+                Ctl::Eval(first, env)
+            }
+        }
+    }
+
+    fn eval_symbol(&self, s: Symbol, env: &Env) -> Res {
+        let (entry, scope) = env.find(s)?;
+        self.value_of(s, &entry, scope)
+    }
+
+    /// The value of `name`, bound to `entry` in `scope`. A procedure's value
+    /// keeps `scope`, and an import is followed to its module.
+    fn value_of(&self, name: Symbol, entry: &Entry, scope: &Env) -> Res {
+        match entry {
+            Entry::Val(v) => Ok(v.clone()),
+            Entry::Import(m, exported) => {
+                let menv = self.module_env(*m)?;
+                let (entry, scope) = menv.find(*exported)?;
+                self.value_of(*exported, &entry, scope)
+            }
+            _ => Ok(Val::Procedure(Procedure::new(name, entry.clone(), scope.clone()))),
+        }
+    }
+
+    fn module_env(&self, module: Symbol) -> Result<Env, ErrType> {
+        self.modules.get(&module).map(|m| m.env.clone()).ok_or_else(|| ErrType::lookup(module.as_str()))
+    }
+
+    fn eval_quote(rest: &[Form]) -> Res {
+        match rest {
+            [quoted] => Ok(quoted.to_val()),
+            _ => Err(ErrType::syntax("quote takes exactly one form")),
+        }
+    }
+
+    /// `(define name expr)` binds a value; `(define (name . params) body...)`
+    /// defines a procedure.
+    fn eval_define(&mut self, rest: Forms, env: Env) -> Result<Ctl, ErrType> {
+        let target = rest.first().ok_or_else(|| ErrType::syntax("define needs a name"))?;
+        match &target.kind {
+            FormKind::Atom(Val::Symbol(name)) => match &rest[1..] {
+                [expr] => {
+                    self.konts.push(Kont::Define(*name, env.clone()));
+                    Ok(Ctl::Eval(expr.clone(), env))
+                }
+                _ => Err(ErrType::syntax("define takes a name and one expression")),
+            },
+            FormKind::List(sig) if !sig.is_empty() => {
+                let name = *sig[0].to_val().unwrap_symbol()?;
+                let params = Params::parse(&sig[1..])?;
+                env.register_lambda(name, Lambda { params, body: rest.rest() });
+                Ok(Ctl::Return(Val::Nil))
+            }
+            _ => Err(ErrType::syntax("define needs a name")),
+        }
+    }
+
+    /// `(lambda (params...) body...)` makes a procedure taking the lambda
+    /// list `params`, as in `define`, whose body is evaluated in a child of
+    /// the environment the `lambda` form is in.
+    fn eval_lambda(rest: &Forms, env: &Env) -> Res {
+        let params = match rest.first() {
+            Some(Form { kind: FormKind::List(spec), .. }) => Params::parse(spec)?,
+            _ => return Err(ErrType::syntax("lambda needs a lambda list")),
+        };
+        let entry = Entry::Lambda(Rc::new(Lambda { params, body: rest.rest() }));
+        Ok(Val::Procedure(Procedure::new(Symbol::intern("lambda"), entry, env.clone())))
+    }
+
+    /// `(let ((pattern expr) ...) body...)` evaluates each `expr`, then `body`
+    /// with the variables of each pattern bound by destructuring its value.
+    fn eval_let(&mut self, rest: Forms, env: Env) -> Result<Ctl, ErrType> {
+        let bindings = match rest.first() {
+            Some(Form { kind: FormKind::List(bindings), .. }) => Forms::new(bindings, 0),
+            _ => return Err(ErrType::syntax("let needs a list of bindings")),
+        };
+        self.let_bindings(bindings, Vec::new(), env, rest.rest())
+    }
+
+    /// Evaluates the first of `bindings`, or if none are left, `body` with
+    /// `vars` bound.
+    fn let_bindings(&mut self, bindings: Forms, vars: Bindings, env: Env, body: Forms) -> Result<Ctl, ErrType> {
+        let b = match bindings.first() {
+            Some(b) => b,
+            None => {
+                let local = Env::new(Some(&env));
+                for (name, v) in vars {
+                    local.register_symbol(name, v);
+                }
+                return Ok(self.body(body, local));
+            }
+        };
+        let (pattern, expr) = match &b.kind {
+            FormKind::List(items) if items.len() == 2 => {
+                let pattern = Pattern::parse_binding(&items[0]).map_err(|e| e.at(b.span.clone()))?;
+                (pattern, items[1].clone())
+            }
+            _ => return Err(ErrType::syntax("let bindings are (pattern expr)").at(b.span.clone())),
+        };
+        self.konts.push(Kont::Let { bindings, pattern, vars, env: env.clone(), body });
+        Ok(Ctl::Eval(expr, env))
+    }
+
+    /// `(define-record-type name (ctor field...) pred (field accessor [modifier])...)`
+    /// defines a record type with a constructor, a predicate, and for each
+    /// field an accessor and optionally a modifier. Fields the constructor
+    /// doesn't take start out as `#f`; a bare `ctor` takes every field.
+    fn eval_define_record_type(rest: &[Form], env: &Env) -> Res {
+        let malformed = || ErrType::syntax("malformed define-record-type");
+        let (name, ctor, pred, specs) = match rest {
+            [name, ctor, pred, specs @ ..] => (name.to_val(), ctor.to_val(), pred.to_val(), specs),
+            _ => return Err(malformed()),
+        };
+        let name = *name.unwrap_symbol()?;
+        let mut fields = Vec::with_capacity(specs.len());
+        let mut procs = Vec::new();
+        for (i, spec) in specs.iter().enumerate() {
+            let spec = spec.to_val();
+            match &**spec.unwrap_list()? {
+                [field, names @ ..] if !names.is_empty() && names.len() <= 2 => {
+                    fields.push(*field.unwrap_symbol()?);
+                    procs.push((*names[0].unwrap_symbol()?, i, false));
+                    if let Some(m) = names.get(1) {
+                        procs.push((*m.unwrap_symbol()?, i, true));
+                    }
+                }
+                _ => return Err(malformed()),
+            }
+        }
+        let (ctor, positions) = match &ctor {
+            Val::Symbol(s) => (*s, (0..fields.len()).collect()),
+            Val::List(l) if !l.is_empty() => {
+                let positions = l[1..].iter()
+                    .map(|f| {
+                        let f = f.unwrap_symbol()?;
+                        fields.iter().position(|x| x == f).ok_or_else(|| {
+                            ErrType::syntax(&format!("{} is not a field of {}", f, name))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                (*l[0].unwrap_symbol()?, positions)
+            }
+            _ => return Err(malformed()),
+        };
+        let rtype = Rc::new(RecordType { name, fields });
+        env.register_record_proc(ctor, RecordProc::Constructor(Rc::clone(&rtype), positions));
+        env.register_record_proc(*pred.unwrap_symbol()?, RecordProc::Predicate(Rc::clone(&rtype)));
+        for (proc_name, i, modifier) in procs {
+            let p = if modifier {
+                RecordProc::Modifier(Rc::clone(&rtype), i)
+            } else {
+                RecordProc::Accessor(Rc::clone(&rtype), i)
+            };
+            env.register_record_proc(proc_name, p);
+        }
+        Ok(Val::Nil)
+    }
+
+    /// `(guard (var clause...) body...)` evaluates `body`. If that raises, the
+    /// raised value is bound to `var` and the clauses are tried as in `cond`;
+    /// if none matches, the error is raised again.
+    fn eval_guard(&mut self, rest: Forms, span: &Span, env: Env) -> Result<Ctl, ErrType> {
+        let (var, clauses) = match rest.first().map(|spec| &spec.kind) {
+            Some(FormKind::List(items)) if !items.is_empty() => {
+                (*items[0].to_val().unwrap_symbol()?, Forms::new(items, 1))
+            }
+            _ => return Err(ErrType::syntax("guard needs a variable and clauses")),
+        };
+        self.konts.push(Kont::Guard { var, clauses, env: env.clone(), span: span.clone() });
+        Ok(self.body(rest.rest(), env))
+    }
+
+    /// Evaluates the first clause whose test is true, as in `cond`. A clause
+    /// is `(test body...)`, `(else body...)` or `(test => f)`, which calls
+    /// the procedure `f` evaluates to with the test's value. If no test is true,
+    /// `otherwise` is raised if given, and the result is nil if not.
+    fn clauses(&mut self, clauses: Forms, env: Env, span: Span, otherwise: Option<ErrType>) -> Result<Ctl, ErrType> {
+        let clause = match clauses.first() {
+            Some(clause) => clause,
+            None => return Ok(otherwise.map_or(Ctl::Return(Val::Nil), Ctl::Raise)),
+        };
+        let test = match &clause.kind {
+            FormKind::List(items) if !items.is_empty() => items[0].clone(),
+            _ => return Err(ErrType::syntax("malformed clause").at(clause.span.clone())),
+        };
+        match &test.kind {
+            FormKind::Atom(Val::Symbol(s)) if s.as_str() == "else" => {
+                self.clause(Val::Bool(true), clauses, env, span, otherwise)
+            }
+            _ => {
+                self.konts.push(Kont::Clause { clauses, env: env.clone(), span, otherwise });
+                Ok(Ctl::Eval(test, env))
+            }
+        }
+    }
+
+    /// Carries on from the test of the first of `clauses`, which gave `test`.
+    fn clause(&mut self, test: Val, clauses: Forms, env: Env, span: Span, otherwise: Option<ErrType>) -> Result<Ctl, ErrType> {
+        if !test.is_truthy() {
+            return self.clauses(clauses.rest(), env, span, otherwise);
+        }
+        let items = match &clauses[0].kind {
+            FormKind::List(items) => Rc::clone(items),
+            _ => unreachable!("clauses are checked before their test is evaluated"),
+        };
+        match &items[1..] {
+            [] => Ok(Ctl::Return(test)),
+            [arrow, f] if arrow.to_val() == Val::Symbol(Symbol::intern("=>")) => {
+                self.konts.push(Kont::Apply { args: vec![test], span: clauses[0].span.clone() });
+                Ok(Ctl::Eval(f.clone(), env))
+            }
+            _ => Ok(self.body(Forms::new(&items, 1), env)),
+        }
+    }
+
+    /// `(if test then [else])`; a missing `else` branch makes `if` return nil.
+    fn eval_if(&mut self, rest: Forms, env: Env) -> Result<Ctl, ErrType> {
+        let (test, then, otherwise) = match &rest[..] {
+            [test, then] => (test.clone(), then.clone(), None),
+            [test, then, otherwise] => (test.clone(), then.clone(), Some(otherwise.clone())),
+            _ => return Err(ErrType::syntax("if takes a test, a consequent and an optional alternative")),
+        };
+        self.konts.push(Kont::If { then, otherwise, env: env.clone() });
+        Ok(Ctl::Eval(test, env))
+    }
+
+    /// `(and x...)` returns the first false value, or the last value, without
+    /// evaluating the rest; `(and)` is `#t`.
+    fn and(&mut self, forms: Forms, env: Env) -> Ctl {
+        match &forms[..] {
+            [] => Ctl::Return(Val::Bool(true)),
+            [last] => Ctl::Eval(last.clone(), env),
+            [first, ..] => {
+                let first = first.clone();
+                self.konts.push(Kont::And(forms.rest(), env.clone()));
+                Ctl::Eval(first, env)
+            }
+        }
+    }
+
+    /// `(or x...)` returns the first true value without evaluating the rest,
+    /// or `#f` if there isn't one.
+    fn or(&mut self, forms: Forms, env: Env) -> Ctl {
+        match &forms[..] {
+            [] => Ctl::Return(Val::Bool(false)),
+            [last] => Ctl::Eval(last.clone(), env),
+            [first, ..] => {
+                let first = first.clone();
+                self.konts.push(Kont::Or(forms.rest(), env.clone()));
+                Ctl::Eval(first, env)
+            }
+        }
+    }
+
+    /// `(when test body...)` evaluates `body` if `test` is true, and
+    /// `unless` if it's false; otherwise they return nil.
+    fn eval_when(&mut self, rest: Forms, expected: bool, env: Env) -> Result<Ctl, ErrType> {
+        let test = rest.first().ok_or_else(|| ErrType::syntax("when and unless need a test"))?.clone();
+        self.konts.push(Kont::When { expected, body: rest.rest(), env: env.clone() });
+        Ok(Ctl::Eval(test, env))
+    }
+
+    /// `(handler-bind ((pred handler) ...) body...)` evaluates `body` with
+    /// the given handlers active, earlier clauses taking precedence. The
+    /// predicates and handlers are expressions giving procedures, evaluated
+    /// on entering `handler-bind`.
+    fn eval_handler_bind(&mut self, rest: Forms, env: Env) -> Result<Ctl, ErrType> {
+        let bindings = rest.first().ok_or_else(|| ErrType::syntax("handler-bind needs a list of handlers"))?;
+        let clauses = match &bindings.kind {
+            FormKind::List(clauses) => clauses,
+            _ => return Err(ErrType::type_error("list", bindings.to_val().val_type())),
+        };
+        let mut forms = Vec::with_capacity(2 * clauses.len());
+        for c in clauses.iter() {
+            match &c.kind {
+                FormKind::List(pair) if pair.len() == 2 => forms.extend(pair.iter().cloned()),
+                _ => return Err(ErrType::syntax("handler-bind clauses are (predicate handler)")),
+            }
+        }
+        self.gather(forms.into(), env, Gathered::HandlerBind(rest.rest()))
+    }
+
+    /// The active handlers, innermost first, each with the depth of the frame
+    /// establishing it.
+    fn handlers(&self) -> Vec<(Handler, usize)> {
+        let mut enabled = self.konts.len();
+        let mut found = Vec::new();
+        for (depth, k) in self.konts.iter().enumerate().rev() {
+            match k {
+                Kont::Handling { depth: running, .. } => enabled = enabled.min(*running),
+                Kont::Handlers(hs) if depth < enabled => found.extend(hs.iter().map(|h| (h.clone(), depth))),
+                _ => (),
+            }
+        }
+        found
+    }
+
+    /// Offers `err`, raised at `span` by a builtin or by the evaluator, to the
+    /// active handlers before the stack unwinds, innermost first. While a
+    /// handler runs, it and the handlers inside it are disabled. A handler
+    /// declines by returning; it can instead invoke the `use-value` restart to
+    /// make the failed call or form return a value, or invoke a restart
+    /// established by an enclosing `restart-case`. Fatal errors aren't
+    /// offered to handlers.
+    fn signal(&mut self, err: ErrType, span: &Span) -> Ctl {
+        let err = err.at(span.clone());
+        let handlers = if err.is_fatal() { Vec::new() } else { self.handlers() };
+        if handlers.is_empty() {
+            return Ctl::Raise(err);
+        }
+        let err = err.traced(&self.backtrace());
+        let cond = err.to_val();
+        self.konts.push(Kont::Signal { err, cond, handlers: handlers.into(), span: span.clone() });
+        self.offer(0, true).unwrap_or_else(Ctl::Raise)
+    }
+
+    /// Calls the predicate, or if `pred` is false the handler, of the `i`th
+    /// handler of the signal on top of the stack with its condition. Once
+    /// every handler has declined, the signal's error is raised.
+    fn offer(&mut self, i: usize, pred: bool) -> Result<Ctl, ErrType> {
+        let next = match self.konts.last() {
+            Some(Kont::Signal { cond, handlers, span, .. }) => handlers.get(i).map(|(h, depth)| {
+                let f = if pred { h.pred.clone() } else { h.handler.clone() };
+                (f, *depth, cond.clone(), span.clone())
+            }),
+            _ => unreachable!("handlers run above their signal"),
+        };
+        match next {
+            Some((f, depth, cond, span)) => {
+                self.konts.push(Kont::Handling { i, depth, pred });
+                self.apply_value(&f, vec![cond], &span)
+            }
+            None => match self.konts.pop() {
+                Some(Kont::Signal { err, .. }) => Ok(Ctl::Raise(err)),
+                _ => unreachable!("handlers run above their signal"),
+            },
+        }
+    }
+
+    /// `(restart-case expr (name lambda-list body...) ...)` evaluates `expr`
+    /// with the given restarts available. Invoking one of them unwinds back
+    /// here and evaluates its body with the restart's arguments bound.
+    fn eval_restart_case(&mut self, rest: Forms, env: Env) -> Result<Ctl, ErrType> {
+        let expr = rest.first().ok_or_else(|| ErrType::syntax("restart-case needs an expression"))?.clone();
+        let mut restarts = Vec::with_capacity(rest.len() - 1);
+        for c in rest.rest().iter() {
+            match &c.kind {
+                FormKind::List(items) if items.len() >= 2 => {
+                    let name = *items[0].to_val().unwrap_symbol()?;
+                    let params = match &items[1].kind {
+                        FormKind::List(spec) => Params::parse(spec)?,
+                        _ => return Err(ErrType::syntax("malformed restart clause").at(c.span.clone())),
+                    };
+                    restarts.push(Restart { name, params, body: Forms::new(items, 2) });
+                }
+                _ => return Err(ErrType::syntax("malformed restart clause").at(c.span.clone())),
+            }
+        }
+        self.konts.push(Kont::Restarts(restarts.into(), env.clone()));
+        Ok(Ctl::Eval(expr, env))
+    }
+
+    fn invoke_restart(&mut self, args: Args) -> Result<Ctl, ErrType> {
+        let (name, args) = args.split_first()
+            .ok_or_else(|| ErrType::arity_mismatch("invoke-restart", &[Arity::AtLeast(1)], 0))?;
+        let name = *name.unwrap_symbol()?;
+        let depth = self.konts.iter()
+            .rposition(|k| match k {
+                Kont::Restarts(restarts, _) => restarts.iter().any(|r| r.name == name),
+                Kont::Signal { .. } => name.as_str() == "use-value",
+                _ => false,
+            })
+            .ok_or_else(|| ErrType::lookup(name.as_str()))?;
+        self.unwind(depth + 1, Then::Restart(name, args.to_vec()))
+    }
+
+    /// `(call/cc f)` calls procedure `f` with a continuation `k` of the
+    /// `call/cc` form: calling `(k v)` makes `call/cc` return `v`, even after
+    /// it has returned already, as many times as `k` is called.
+    fn eval_call_cc(&mut self, rest: Forms, span: &Span, env: Env) -> Result<Ctl, ErrType> {
+        match rest.len() {
+            1 => self.gather(rest, env, Gathered::CallCc(span.clone())),
+            n => Err(ErrType::arity_mismatch("call/cc", &[Arity::SomeArgs(1)], n)),
+        }
+    }
+
+    /// `(dynamic-wind before thunk after)` calls procedures `before`, `thunk`
+    /// and `after` in order, returning `thunk`'s value. `after` is called
+    /// however `thunk` exits, including by escaping to a continuation, and
+    /// `before` again whenever a continuation re-enters `thunk`. The exception
+    /// is a fatal error, such as hitting a resource limit, which ends the
+    /// evaluation without calling `after`; see `run`.
+    fn eval_dynamic_wind(&mut self, rest: Forms, span: &Span, env: Env) -> Result<Ctl, ErrType> {
+        match rest.len() {
+            3 => self.gather(rest, env, Gathered::DynamicWind(span.clone())),
+            n => Err(ErrType::arity_mismatch("dynamic-wind", &[Arity::SomeArgs(3)], n)),
+        }
+    }
+
+    /// `(shift k body...)` captures the rest of the computation up to the
+    /// innermost `reset` as continuation `k`, leaves it, and evaluates `body`
+    /// with `k` bound in its place: the `reset` returns the body's value.
+    /// Calling `(k v)` runs the rest with `v` as the value of the `shift`,
+    /// inside a `reset` of its own, and returns what that returns.
+    fn eval_shift(&mut self, rest: Forms, env: Env) -> Result<Ctl, ErrType> {
+        let var = match rest.first().map(Form::to_val) {
+            Some(Val::Symbol(s)) => s,
+            _ => return Err(ErrType::syntax("shift needs a variable")),
+        };
+        let depth = self.konts.iter()
+            .rposition(|k| matches!(k, Kont::Reset))
+            .ok_or_else(|| ErrType::user("shift outside of reset", vec![]))?
+            + 1;
+        let k = Rc::new(Continuation { frames: self.konts[depth..].to_vec(), origin: Some(depth) });
+        self.unwind(depth, Then::Shift { k, var, body: rest.rest(), env })
+    }
+
+    /// Continues into `k` with the one argument. The `dynamic-wind`s being
+    /// left are exited, innermost first, and those `k` is inside of entered,
+    /// outermost first. A delimited `k` leaves nothing; see `compose`.
+    fn throw(&mut self, k: &Rc<Continuation>, args: Args) -> Result<Ctl, ErrType> {
+        let v = match <[Val; 1]>::try_from(args) {
+            Ok([v]) => v,
+            Err(args) => return Err(ErrType::arity_mismatch("continuation", &[Arity::SomeArgs(1)], args.len())),
+        };
+        if let Some(origin) = k.origin {
+            return self.compose(k, origin, v);
+        }
+        let here = winds(&self.konts);
+        let there = winds(&k.frames);
+        let shared = here.iter().zip(&there).take_while(|((_, a), (_, b))| a == b).count();
+        let depth = match shared {
+            0 => 0,
+            n => here[n - 1].0 + 1,
+        };
+        self.unwind(depth, Then::Enter(Rc::clone(k), v, shared))
+    }
+
+    /// Pops frames down to `depth`, calling the after thunk of each
+    /// `dynamic-wind` left on the way, then carries on as `then` says.
+    fn unwind(&mut self, depth: usize, then: Then) -> Result<Ctl, ErrType> {
+        while self.konts.len() > depth {
+            if let Some(Kont::Wind { after, span, .. }) = self.konts.pop() {
+                self.konts.push(Kont::Unwind { depth, then });
+                return self.apply_value(&after, vec![], &span);
+            }
+        }
+        match then {
+            Then::Enter(k, v, next) => self.rewind(k, v, 0, next),
+            Then::Shift { k, var, body, env } => {
+                let local = Env::new(Some(&env));
+                local.register_symbol(var, Val::Continuation(k));
+                Ok(self.body(body, local))
+            }
+            Then::Restart(name, args) => match self.konts.pop() {
+                Some(Kont::Signal { .. }) => match <[Val; 1]>::try_from(args) {
+                    Ok([v]) => Ok(Ctl::Return(v)),
+                    Err(args) => Err(ErrType::arity_mismatch("use-value", &[Arity::SomeArgs(1)], args.len())),
+                },
+                Some(Kont::Restarts(restarts, env)) => {
+                    let r = restarts.iter().find(|r| r.name == name).ok_or_else(|| ErrType::lookup(name.as_str()))?;
+                    self.enter(name, &r.params, args, Env::new(Some(&env)), r.body.clone())
+                }
+                _ => unreachable!("restarts unwind to the frame establishing them"),
+            },
+        }
+    }
+
+    /// Pushes the frames of delimited continuation `k`, captured above depth
+    /// `origin`, inside a new `reset`, and enters them with `v`. Depths they
+    /// refer to among themselves are moved with them, and their
+    /// `dynamic-wind`s get new ids, as they're new activations.
+    fn compose(&mut self, k: &Continuation, origin: usize, v: Val) -> Result<Ctl, ErrType> {
+        self.konts.push(Kont::Reset);
+        let base = self.konts.len();
+        let moved = |depth: usize| if depth >= origin { depth - origin + base } else { depth };
+        let mut frames = Vec::with_capacity(k.frames.len());
+        for f in &k.frames {
+            frames.push(match f.clone() {
+                Kont::Wind { before, after, span, .. } => Kont::Wind { id: self.new_id(), before, after, span },
+                Kont::Handling { i, depth, pred } => Kont::Handling { i, depth: moved(depth), pred },
+                Kont::Signal { err, cond, handlers, span } => {
+                    let handlers = handlers.iter().map(|(h, depth)| (h.clone(), moved(*depth))).collect();
+                    Kont::Signal { err, cond, handlers, span }
+                }
+                Kont::Unwind { depth, then } => Kont::Unwind { depth: moved(depth), then },
+                Kont::Rewind { k, v, base, next } => Kont::Rewind { k, v, base: moved(base), next },
+                f => f,
+            });
+        }
+        let k = Rc::new(Continuation { frames, origin: Some(base) });
+        self.rewind(k, v, base, 0)
+    }
+
+    /// Re-enters continuation `k` with `v`, putting its frames above depth
+    /// `base` and calling the before thunks of its `dynamic-wind`s from the
+    /// `next`th on, each with the stack as it was on entering that
+    /// `dynamic-wind`.
+    fn rewind(&mut self, k: Rc<Continuation>, v: Val, base: usize, next: usize) -> Result<Ctl, ErrType> {
+        let wind = k.frames.iter()
+            .enumerate()
+            .filter_map(|(depth, f)| match f {
+                Kont::Wind { before, span, .. } => Some((depth, before.clone(), span.clone())),
+                _ => None,
+            })
+            .nth(next);
+        match wind {
+            Some((depth, before, span)) => {
+                self.konts.truncate(base);
+                self.konts.extend_from_slice(&k.frames[..depth]);
+                self.konts.push(Kont::Rewind { k, v, base, next: next + 1 });
+                self.apply_value(&before, vec![], &span)
+            }
+            None => {
+                self.konts.truncate(base);
+                self.konts.extend_from_slice(&k.frames);
+                Ok(Ctl::Return(v))
+            }
+        }
+    }
+
+    /// `(update coll k f args...)` is `(assoc coll k (f (get coll k) args...))`
+    /// for a map or pvector `coll` and a procedure `f`.
+    fn eval_update(&mut self, rest: Forms, span: &Span, env: Env) -> Result<Ctl, ErrType> {
+        match rest.len() {
+            n if n >= 3 => self.gather(rest, env, Gathered::Update(span.clone())),
+            n => Err(ErrType::arity_mismatch("update", &[Arity::AtLeast(3)], n)),
+        }
+    }
+
+    /// `(match expr (pattern body...) ...)` evaluates the body of the first
+    /// clause whose pattern matches the value of `expr`, with the pattern's
+    /// variables bound. See `Pattern` for the pattern syntax.
+    fn eval_match(&mut self, rest: Forms, span: &Span, env: Env) -> Result<Ctl, ErrType> {
+        let expr = rest.first().ok_or_else(|| ErrType::syntax("match needs an expression"))?.clone();
+        self.konts.push(Kont::Match { clauses: rest.rest(), env: env.clone(), span: span.clone() });
+        Ok(Ctl::Eval(expr, env))
+    }
+
+    /// Tries `value` against the first of `clauses` onwards, carrying on with
+    /// `matcher` if given. A `(? pred)` predicate is evaluated and called on
+    /// the stack like any procedure, and once it returns, the matcher resumes
+    /// where it stopped.
+    fn try_match(&mut self, value: Val, mut clauses: Forms, mut matcher: Option<Matcher>, env: Env, span: Span) -> Result<Ctl, ErrType> {
+        while let Some(clause) = clauses.first() {
+            let items = match &clause.kind {
+                FormKind::List(items) if !items.is_empty() => Rc::clone(items),
+                _ => return Err(ErrType::syntax("malformed match clause").at(clause.span.clone())),
+            };
+            let mut m = match matcher.take() {
+                Some(m) => m,
+                None => {
+                    let pattern = self.patterns.get(&items).map_err(|e| e.at(clause.span.clone()))?;
+                    Matcher::new(&pattern, value.clone())
+                }
+            };
+            match m.run() {
+                Step::Matched(bindings) => {
+                    let local = Env::new(Some(&env));
+                    for (name, v) in bindings {
+                        local.register_symbol(name, v);
+                    }
+                    return Ok(self.body(Forms::new(&items, 1), local));
+                }
+                Step::Failed => clauses = clauses.rest(),
+                Step::Pred(pred, v) => {
+                    self.konts.push(Kont::MatchPred { matcher: m, value, clauses, env: env.clone(), span: span.clone() });
+                    self.konts.push(Kont::Apply { args: vec![v], span });
+                    return Ok(Ctl::Eval(pred, env));
+                }
+            }
+        }
+        Err(ErrType::no_match(value).at(span))
+    }
+
+    /// `(module name (export name...) body...)` evaluates `body` in a new
+    /// environment whose parent holds just the builtins, making the exported
+    /// names available to `import`.
+    fn eval_module(&mut self, rest: Forms, span: &Span, env: Env) -> Result<Ctl, ErrType> {
+        let (name, exports) = match &rest[..] {
+            [name, exports, ..] => (*name.to_val().unwrap_symbol()?, exports.to_val()),
+            _ => return Err(ErrType::syntax("module needs a name and an export list")),
+        };
+        let exports = match &**exports.unwrap_list()? {
+            [head, names @ ..] if head.unwrap_symbol().ok().map(|s| s.as_str()) == Some("export") => {
+                names.iter().map(|n| n.unwrap_symbol().copied()).collect::<Result<Vec<_>, _>>()?
+            }
+            _ => return Err(ErrType::syntax("module exports are (export name...)")),
+        };
+        let menv = Env::new(Some(env.root()));
+        self.konts.push(Kont::Module { name, exports, env: menv.clone(), span: span.clone() });
+        Ok(self.body(rest.rest().rest(), menv))
+    }
+
+    /// The modules being defined, outermost first.
+    fn loading(&self) -> Vec<Symbol> {
+        let mut loading = Vec::new();
+        for k in &self.konts {
+            let name = match k {
+                Kont::Module { name, .. } => *name,
+                #[cfg(feature = "io")]
+                Kont::LoadModule { name, .. } => *name,
+                _ => continue,
+            };
+            // A module loaded from its file is defined inside the load.
+            if loading.last() != Some(&name) {
+                loading.push(name);
+            }
+        }
+        loading
+    }
+
+    /// Raises a `CycleError` if `module` is still being defined, so importing
+    /// it would see it half-finished.
+    fn check_cycle(&self, module: Symbol) -> Result<(), ErrType> {
+        let loading = self.loading();
+        match loading.iter().position(|m| *m == module) {
+            Some(i) => {
+                let mut path: Vec<&str> = loading[i..].iter().map(|m| m.as_str()).collect();
+                path.push(module.as_str());
+                Err(ErrType::cycle(&path))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// `(import set...)` binds the names each import set selects; see
+    /// `ImportSet`. With the `io` feature, a module that isn't defined yet is
+    /// loaded from its file, which is evaluated once, in a new environment,
+    /// and must define it.
+    #[cfg_attr(not(feature = "io"), allow(unused_variables))]
+    fn import(&mut self, mut sets: Forms, span: &Span, env: Env) -> Result<Ctl, ErrType> {
+        while let Some(form) = sets.first() {
+            let set = ImportSet::parse(&form.to_val())?;
+            let m = set.module();
+            self.check_cycle(m)?;
+            #[cfg(feature = "io")]
+            if !self.modules.contains_key(&m) && !self.sandboxed {
+                let path = module::find_file(&module::module_file(m), &self.search_path)?;
+                let forms = Evaluator::read_file(&path)?;
+                let fenv = Env::new(Some(env.root()));
+                self.konts.push(Kont::Import { sets, env, span: span.clone() });
+                self.konts.push(Kont::LoadModule { name: m, path, span: span.clone() });
+                self.konts.push(Kont::TopLevel(fenv.clone()));
+                return Ok(self.body(forms, fenv));
+            }
+            for (local, module, exported) in set.resolve(&self.modules)? {
+                env.register_import(local, module, exported);
+            }
+            sets = sets.rest();
+        }
+        Ok(Ctl::Return(Val::Nil))
+    }
+
+    #[cfg(feature = "io")]
+    fn read_file(path: &Path) -> Result<Forms, ErrType> {
+        let file = path.to_string_lossy();
+        let source = fs::read_to_string(path)
+            .map_err(|e| ErrType::user(&format!("can't read {}: {}", file, e), vec![]))?;
+        Ok(Forms::from(reader::read(&source, &file)?))
+    }
+
+    /// `(load "file")` evaluates the forms in `file`, found on the search
+    /// path, at top level; see `Kont::TopLevel`.
+    #[cfg(feature = "io")]
+    fn eval_load(&mut self, args: Args) -> Result<Ctl, ErrType> {
+        let file = match &args[..] {
+            [file] => file.unwrap_string()?,
+            _ => return Err(ErrType::arity_mismatch("load", &[Arity::SomeArgs(1)], args.len())),
+        };
+        let path = module::find_file(Path::new(&file), &self.search_path)?;
+        let forms = Evaluator::read_file(&path)?;
+        let env = self.konts.iter()
+            .rev()
+            .find_map(|k| match k {
+                Kont::TopLevel(env) => Some(env.clone()),
+                _ => None,
+            })
+            .expect("eval_str evaluates everything above a TopLevel frame");
+        self.konts.push(Kont::Value(Val::Nil));
+        Ok(self.body(forms, env))
+    }
+
+    /// Calls primitive `p`. A sandboxed evaluator doesn't have `load`.
+    fn apply_primitive(&mut self, p: Primitive, args: Args) -> Result<Ctl, ErrType> {
+        match p {
+            Primitive::InvokeRestart => self.invoke_restart(args),
+            #[cfg(feature = "io")]
+            Primitive::Load if self.sandboxed => Err(ErrType::lookup("load")),
+            #[cfg(feature = "io")]
+            Primitive::Load => self.eval_load(args),
+        }
+    }
+
+    /// Binds `args` to `params` in `local`, as when calling `name`, and
+    /// evaluates `body` there. The defaults of missing optional arguments are
+    /// evaluated first, in `local`, so they can refer to earlier parameters.
+    fn enter(&mut self, name: Symbol, params: &Params, args: Args, local: Env, body: Forms) -> Result<Ctl, ErrType> {
+        let missing = local.bind(name.as_str(), params, args)?;
+        if missing.is_empty() {
+            return Ok(self.body(body, local));
+        }
+        Ok(self.defaults(missing.into(), 0, local, body))
+    }
+
+    /// Binds the `next`th of the `missing` optional parameters onwards to
+    /// their defaults, then evaluates `body`.
+    fn defaults(&mut self, missing: Rc<[(Symbol, Option<Form>)]>, mut next: usize, local: Env, body: Forms) -> Ctl {
+        while let Some((name, default)) = missing.get(next) {
+            if let Some(expr) = default {
+                let expr = expr.clone();
+                self.konts.push(Kont::Defaults { missing, next, local: local.clone(), body });
+                return Ctl::Eval(expr, local);
+            }
+            local.register_symbol(*name, Val::Bool(false));
+            next += 1;
+        }
+        self.body(body, local)
+    }
+
+    /// Calls the procedure bound to `name` in `env`.
+    fn apply_named(&mut self, name: Symbol, args: Args, span: &Span, env: &Env) -> Result<Ctl, ErrType> {
+        let (entry, scope) = env.find(name)?;
+        self.apply_entry(name, &entry, scope, args, span)
+    }
+
+    /// Calls `f`, which should be a procedure or a continuation.
+    fn apply_value(&mut self, f: &Val, args: Args, span: &Span) -> Result<Ctl, ErrType> {
+        match f {
+            Val::Procedure(p) => self.apply_entry(p.name, &p.entry, &p.env, args, span),
+            Val::Continuation(k) => self.throw(k, args),
+            _ => Err(ErrType::not_a_function(&f.to_string())),
+        }
+    }
+
+    /// Calls `name`, bound to `entry` in `scope`. Errors the call raises are
+    /// signalled before the stack unwinds.
+    fn apply_entry(&mut self, name: Symbol, entry: &Entry, scope: &Env, args: Args, span: &Span) -> Result<Ctl, ErrType> {
+        // Builtins and record constructors allocate; count how much bigger
+        // their result is than their arguments, and a cell for each char of
+        // the names of symbols they create, which are never freed.
+        let arg_cells: u64 = match (entry, self.limits.cells) {
+            (Entry::Func(_), Some(_)) | (Entry::Record(_), Some(_)) => args.iter().map(cells).sum(),
+            _ => 0,
+        };
+        let interned = Symbol::interned_len();
+        let res = match entry {
+            Entry::Func(f) => f.call(name.as_str(), args),
+            Entry::Record(p) => p.call(name.as_str(), args),
+            Entry::Lambda(l) => {
+                // A call made as the last thing a procedure does replaces
+                // that procedure's frame, so tail calls run in constant space.
+                if let Some(Kont::Call(_)) = self.konts.last() {
+                    self.konts.pop();
+                }
+                self.konts.push(Kont::Call(Frame { name: name.to_string(), span: span.clone() }));
+                let local = Env::new(Some(scope));
+                return Ok(self.enter(name, &l.params, args, local, l.body.clone()).unwrap_or_else(|e| self.signal(e, span)));
+            }
+            Entry::Val(v @ Val::Procedure(_)) | Entry::Val(v @ Val::Continuation(_)) => {
+                return self.apply_value(v, args, span);
+            }
+            Entry::Primitive(p) => return self.apply_primitive(*p, args),
+            Entry::Val(_) => return Err(ErrType::not_a_function(name.as_str())),
+            Entry::Import(m, exported) => {
+                let menv = self.module_env(*m)?;
+                let (entry, scope) = menv.find(*exported)?;
+                return self.apply_entry(*exported, &entry, scope, args, span);
+            }
+        };
+        let res = match res {
+            Ok(v) if self.limits.cells.is_some() => {
+                let names = Symbol::interned_len() - interned;
+                self.allocate(cells(&v).saturating_sub(arg_cells) + names).map(|_| v)
+            }
+            Err(e) => {
+                self.konts.push(Kont::Call(Frame { name: name.to_string(), span: span.clone() }));
+                return Ok(self.signal(e, span));
+            }
+            ok => ok,
+        };
+        match res {
+            Ok(v) => Ok(Ctl::Return(v)),
+            Err(e) => {
+                self.konts.push(Kont::Call(Frame { name: name.to_string(), span: span.clone() }));
+                Ok(Ctl::Raise(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::stdlib::Builder;
+    use crate::stdlib::core::core;
+
+    fn eval(src: &str) -> Res {
+        let core = core();
+        let mut env = Env::new(Some(&core));
+        Evaluator::new().eval_str(src, "test", &mut env)
+    }
+
+    #[test]
+    fn test_eval_call() -> Result<(), ErrType> {
+        assert_eq!(eval("(+ 1 (* 2 3))")?, Val::Int(7));
+        assert_eq!(eval("(car '(1 2))")?, Val::Int(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_collection_literals() -> Result<(), ErrType> {
+        assert_eq!(*eval("#((+ 1 2) 'a)")?.unwrap_vector()?.borrow(), vec![Val::Int(3), eval("'a")?]);
+        assert_eq!(eval("(define x 1) {x (+ x 1)}")?, eval("'{1 2}")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update() -> Result<(), ErrType> {
+        assert_eq!(eval("(update {1 2} 1 + 10)")?, eval("{1 12}")?);
+        assert_eq!(eval("(update (pvector 1 2) 1 list)")?, eval("(pvector 1 '(2))")?);
+        assert_eq!(eval("(update {} 'a list)")?, eval("{'a '(#f)}")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_set_literal() -> Result<(), ErrType> {
+        assert_eq!(eval("#{(+ 1 1) 2 'a}")?, eval("(set 'a 2)")?);
+        assert_eq!(eval("(get {#{1 2} 'x} #{2 1})")?, eval("'x")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_define_record_type() -> Result<(), ErrType> {
+        let defs = "(define-record-type point (make-point x y) point? (x point-x set-point-x!) (y point-y)) \
+                    (define-record-type tag (make-tag) tag? (label tag-label)) ";
+        let eval_with = |src: &str| eval(&format!("{}{}", defs, src));
+        assert_eq!(eval_with("(point-y (make-point 1 2))")?, Val::Int(2));
+        assert_eq!(eval_with("(define p (make-point 1 2)) (set-point-x! p 5) (point-x p)")?, Val::Int(5));
+        assert_eq!(eval_with("(list (point? (make-point 1 2)) (point? (make-tag)) (point? 1))")?,
+                   eval("'(#t #f #f)")?);
+        assert_eq!(eval_with("(tag-label (make-tag))")?, Val::Bool(false));
+        assert_eq!(eval_with("(make-point 1 2)")?.to_string(), "#<record point>");
+        assert_eq!(eval_with("(define p (make-point 1 2)) (list (set-member? (set p) p) (set-member? (set p) (make-point 1 2)))")?,
+                   eval("'(#t #f)")?);
+        let err = eval_with("(point-x (make-tag))").unwrap_err();
+        assert_eq!(err.root(), &ErrType::type_error("point", "tag"));
+        let err = eval_with("(make-point 1)").unwrap_err();
+        assert_eq!(err.root(), &ErrType::arity_mismatch("make-point", &[Arity::SomeArgs(2)], 1));
+        assert!(eval("(define-record-type p (make-p z) p? (x p-x))").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_nil() -> Result<(), ErrType> {
+        assert_eq!(eval("(define x 1)")?, Val::Nil);
+        assert_eq!(eval("(define (f)) (f)")?, Val::Nil);
+        let src = "(define (f)) (list (nil? (define y 2)) (null? '()) (null? (f)))";
+        assert_eq!(eval(src)?, eval("'(#t #t #f)")?);
+        assert!(eval("(car '())").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lambda() -> Result<(), ErrType> {
+        assert_eq!(eval("((lambda (x y) (+ x y)) 1 2)")?, Val::Int(3));
+        assert_eq!(eval("((lambda ((a b) #!optional (c (+ a b)) . rest) (list c rest)) '(1 2))")?,
+                   eval("'(3 ())")?);
+        let src = "(define (adder n) (lambda (x) (+ x n)))
+                   (define add2 (adder 2))
+                   (list (add2 1) ((adder 10) 1) (type-of add2))";
+        assert_eq!(eval(src)?, eval("'(3 11 procedure)")?);
+        assert_eq!(eval("(call/cc (lambda (k) (+ 1 (k 5))))")?, Val::Int(5));
+        let err = eval("((lambda (x) x))").unwrap_err();
+        assert_eq!(err.root(), &ErrType::arity_mismatch("lambda", &[Arity::SomeArgs(1)], 0));
+        assert!(eval("(lambda x x)").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_of_procedure() -> Result<(), ErrType> {
+        let src = "(define (f) 1) (define-record-type tag (make-tag) tag?) (define g f)
+                   (list (type-of +) (type-of f) (type-of g) (type-of tag?))";
+        assert_eq!(eval(src)?, eval("'(procedure procedure procedure procedure)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_predicates() -> Result<(), ErrType> {
+        let src = "(define (f) 1) (define x 1) (define (g k) (procedure? k)) \
+                   (list (procedure? f) (procedure? car) (procedure? x) (procedure? 1) (call/cc g))";
+        assert_eq!(eval(src)?, eval("'(#t #t #f #f #t)")?);
+        assert_eq!(eval("(type-of (list->vector '(1)))")?, eval("'vector")?);
+        assert_eq!(eval("(define-record-type point (make-point) point?) (type-of (make-point))")?, eval("'point")?);
+        assert_eq!(eval("(integer->char (+ (char->integer #\\a) 1))")?, Val::Char('b'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_conditionals() -> Result<(), ErrType> {
+        assert_eq!(eval("(if '() 1 2)")?, Val::Int(1));
+        assert_eq!(eval("(if #f 1)")?, Val::Nil);
+        assert_eq!(eval("(list (and) (and 1 2) (and 1 #f (car '())))")?, eval("'(#t 2 #f)")?);
+        assert_eq!(eval("(list (or) (or #f 2 (car '())) (not 0))")?, eval("'(#f 2 #f)")?);
+        assert_eq!(eval("(list (when #t 1 2) (unless #t 1) (unless #f 3))")?, eval("(list 2 (if #f #f) 3)")?);
+        assert_eq!(eval("(cond (#f 1) ((+ 1 1) => list) (else 3))")?, eval("'(2)")?);
+        assert_eq!(eval("(cond (5 => (lambda (v) (+ v 1))))")?, Val::Int(6));
+        assert_eq!(eval("(cond (#f 1) (else 2 3))")?, Val::Int(3));
+        assert_eq!(eval("(cond (#f 1) (5))")?, Val::Int(5));
+        assert_eq!(eval("(cond (#f 1))")?, Val::Nil);
+        assert_eq!(eval("(guard (e ((get e 'code) => list)) (raise {'code 7}))")?, eval("'(7)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cond_arrow_errors() {
+        let err = eval("(cond (1 => nope))").unwrap_err();
+        assert_eq!(err.to_string(), "test:1:13: nope not found");
+        let err = eval("(cond\n  (1 => (lambda () 0)))").unwrap_err();
+        assert_eq!(err.root(), &ErrType::arity_mismatch("lambda", &[Arity::NoArgs], 1));
+        assert_eq!(err.span().map(|s| (s.line, s.col)), Some((2, 3)));
+    }
+
+    #[test]
+    fn test_match() -> Result<(), ErrType> {
+        let src = "(define (area shape) \
+                     (match shape \
+                       (('circle r) (* 3 r r)) \
+                       (('rect w h) (* w h)) \
+                       (('poly (? int? n) . _) n) \
+                       (('group s ...) (list 'group s)))) ";
+        let area = |shape: &str| eval(&format!("{}(area '{})", src, shape));
+        assert_eq!(area("(circle 2)")?, Val::Int(12));
+        assert_eq!(area("(rect 2 3)")?, Val::Int(6));
+        assert_eq!(area("(poly 5 a b)")?, Val::Int(5));
+        assert_eq!(area("(group a b)")?, eval("'(group (a b))")?);
+        assert_eq!(eval("(match 1 ((? (lambda (x) #t)) 'ok))")?, eval("'ok")?);
+        assert_eq!(eval("(define (f ok) (match 1 ((? (lambda (x) ok)) 'yes) (_ 'no))) (list (f #t) (f #f))")?,
+                   eval("'(yes no)")?);
+        let err = area("(poly a)").unwrap_err();
+        assert_eq!(err.root(), &ErrType::no_match(eval("'(poly a)")?));
+        assert_eq!(err.root().to_string(), "No pattern matches ('poly, 'a)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_destructuring() -> Result<(), ErrType> {
+        assert_eq!(eval("(let ((x 1) ((a b . r) '(2 3 4 5))) (list x a b r))")?, eval("'(1 2 3 (4 5))")?);
+        assert_eq!(eval("(let (((_ (y)) '(1 (2)))) y)")?, Val::Int(2));
+        assert_eq!(eval("(define (f (x y) z) (list y x z)) (f '(1 2) 3)")?, eval("'(2 1 3)")?);
+        let err = eval("(let (((a b) '(1))) a)").unwrap_err();
+        assert_eq!(err.root(), &ErrType::no_match(eval("'(1)")?));
+        assert_eq!(err.span().map(|s| s.col), Some(7));
+        let err = eval("(define (f (x y)) x) (f 1)").unwrap_err();
+        assert_eq!(err.root(), &ErrType::no_match(Val::Int(1)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_modules() -> Result<(), ErrType> {
+        let defs = "(module geometry (export square area origin) \
+                      (define (helper x) (* x x)) \
+                      (define (square x) (helper x)) \
+                      (define (area w h) (* w h)) \
+                      (define origin '(0 0))) \
+                    (module shapes (export unit-area) \
+                      (import (only geometry area)) \
+                      (define (unit-area) (area 1 1))) ";
+        let eval_with = |src: &str| eval(&format!("{}{}", defs, src));
+        assert_eq!(eval_with("(import geometry) (list (square 3) origin)")?, eval("'(9 (0 0))")?);
+        assert_eq!(eval_with("(import (prefix geometry g:)) (g:area 2 3)")?, Val::Int(6));
+        assert_eq!(eval_with("(import (rename (except geometry area) (square sq))) (sq 2)")?, Val::Int(4));
+        assert_eq!(eval_with("(import shapes) (unit-area)")?, Val::Int(1));
+        assert_eq!(eval_with("(define (helper x) 0) (import geometry) (square 2)")?, Val::Int(4));
+        assert_eq!(eval_with("(import geometry) (helper 2)").unwrap_err().root(), &ErrType::lookup("helper"));
+        assert_eq!(eval_with("(import shapes) (area 1 1)").unwrap_err().root(), &ErrType::lookup("area"));
+        assert_eq!(eval_with("(import (only geometry helper))").unwrap_err().root(), &ErrType::lookup("helper"));
+        assert_eq!(eval("(define x 1) (module m (export y) (define y x))").unwrap_err().root(), &ErrType::lookup("x"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_module_cycle() {
+        let err = eval("(module a (export) (module b (export) (import a)))").unwrap_err();
+        assert_eq!(err.root(), &ErrType::cycle(&["a", "b", "a"]));
+    }
+
+    /// A fresh temporary directory holding `files`, as (path, source) pairs.
+    #[cfg(feature = "io")]
+    fn temp_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lisp-{}-{}", name, std::process::id()));
+        for (path, source) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    #[cfg(feature = "io")]
+    fn test_load_and_import_files() -> Result<(), ErrType> {
+        let dir = temp_dir("load", &[
+            ("util.lisp", "(define (twice x) (* 2 x))"),
+            ("lib/counter.lisp", "(load \"util.lisp\") (module lib/counter (export next) \
+                                   (define (next x) (+ x 1)))"),
+            ("lib/noisy.lisp", "(module lib/noisy (export) (define-record-type t (t) t?)) (undefined)"),
+            ("cycle/a.lisp", "(module cycle/a (export) (import cycle/b))"),
+            ("cycle/b.lisp", "(import cycle/a) (module cycle/b (export))"),
+        ]);
+        let core = core();
+        let eval_in = |src: &str| {
+            let mut env = Env::new(Some(&core));
+            let mut ev = Evaluator::new();
+            ev.set_search_path(vec![PathBuf::from("/nonexistent"), dir.clone()]);
+            ev.eval_str(src, "test", &mut env)
+        };
+        assert_eq!(eval_in("(load \"util.lisp\") (twice 2)")?, Val::Int(4));
+        assert_eq!(eval_in("(import lib/counter) (import (prefix lib/counter c:)) (c:next (next 1))")?, Val::Int(3));
+        let err = eval_in("(import lib/missing)").unwrap_err();
+        let expected = format!("lib/missing.lisp not found in /nonexistent, {}", dir.display());
+        assert_eq!(err.root().to_string(), expected);
+        assert_eq!(eval_in("(import lib/noisy)").unwrap_err().root(), &ErrType::lookup("undefined"));
+        assert_eq!(eval_in("(import cycle/a)").unwrap_err().root(), &ErrType::cycle(&["cycle/a", "cycle/b", "cycle/a"]));
+        fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+
+    fn eval_limited(src: &str, limits: Limits) -> Res {
+        let core = Builder::sandbox().build();
+        let mut env = Env::new(Some(&core));
+        Evaluator::sandboxed(limits).eval_str(src, "test", &mut env)
+    }
+
+    #[test]
+    fn test_limits() -> Result<(), ErrType> {
+        let fuel = Limits { fuel: Some(10), ..Limits::default() };
+        assert_eq!(eval_limited("(+ 1 (+ 2 3))", fuel)?, Val::Int(6));
+        let err = eval_limited("(define (f x) (+ x 1)) (f (f (f (f 1))))", fuel).unwrap_err();
+        assert_eq!(err.root(), &ErrType::resource_limit(Resource::Fuel, 10));
+        let depth = Limits { depth: Some(100), ..Limits::default() };
+        let err = eval_limited("(define (f) (list (f))) (guard (e (#t 'caught)) (f))", depth).unwrap_err();
+        assert_eq!(err.root(), &ErrType::resource_limit(Resource::Depth, 100));
+        let cells = Limits { cells: Some(5), ..Limits::default() };
+        assert_eq!(eval_limited("(car (cdr (list 1 2 3)))", cells)?, Val::Int(2));
+        let err = eval_limited("(list 1 2 3) (vector 1 2 3)", cells).unwrap_err();
+        assert_eq!(err.root(), &ErrType::resource_limit(Resource::Cells, 5));
+        assert!(eval_limited("'(1 2 3 4 5 6)", cells).is_err());
+        assert_eq!(eval_limited("(string->symbol \"car\")", cells)?, eval("'car")?);
+        let err = eval_limited("(string->symbol \"a-new-symbol\")", cells).unwrap_err();
+        assert_eq!(err.root(), &ErrType::resource_limit(Resource::Cells, 5));
+        let err = eval_limited("(gensym) (gensym) (gensym)", cells).unwrap_err();
+        assert_eq!(err.root(), &ErrType::resource_limit(Resource::Cells, 5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_depth() -> Result<(), ErrType> {
+        let err = eval("(define (f) (list (f))) (f)").unwrap_err();
+        assert_eq!(err.root(), &ErrType::resource_limit(Resource::Depth, Limits::DEFAULT_DEPTH as u64));
+        let src = "(define (count n) (match n (0 0) (_ (+ 1 (count (- n 1)))))) (count 10000)";
+        assert_eq!(eval(src)?, Val::Int(10000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tail_calls() -> Result<(), ErrType> {
+        let src = "(define (loop n) (match n (0 'done) (_ (loop (- n 1))))) (loop 1000000)";
+        assert_eq!(eval(src)?, eval("'done")?);
+        let src = "(define (even? n) (match n (0 #t) (_ (odd? (- n 1)))))
+                   (define (odd? n) (match n (0 #f) (_ (even? (- n 1)))))
+                   (list (even? 200001) (odd? 200001))";
+        assert_eq!(eval(src)?, eval("'(#f #t)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let limits = Limits::default();
+        assert_eq!(eval_limited("(read-file \"/etc/passwd\")", limits).unwrap_err().root(),
+                   &ErrType::lookup("read-file"));
+        assert_eq!(eval_limited("(load \"x.lisp\")", limits).unwrap_err().root(), &ErrType::lookup("load"));
+        assert_eq!(eval_limited("(import lib/x)", limits).unwrap_err().root(), &ErrType::lookup("lib/x"));
+        assert_eq!(eval_limited("(module m (export f) (define (f) 1)) (import m) (f)", limits), Ok(Val::Int(1)));
+        for src in ["(/ 1 0)", "(* 9223372036854775807 2)", "(- -9223372036854775808)"] {
+            let err = eval_limited(src, limits).unwrap_err();
+            assert!(matches!(err.root(), ErrType::ArithmeticError(_)), "{}: {}", src, err);
+        }
+        let src = "(guard (e (#t (error-object-message e))) (/ 1 0))";
+        assert_eq!(eval_limited(src, limits), Ok(Val::string("Arithmetic error: division by zero in /")));
+        let err = eval_limited("(define (f) (list (f))) (f)", limits).unwrap_err();
+        assert_eq!(err.root(), &ErrType::resource_limit(Resource::Depth, Limits::DEFAULT_DEPTH as u64));
+    }
+
+    #[test]
+    fn test_interrupt() -> Result<(), ErrType> {
+        let core = core();
+        let mut env = Env::new(Some(&core));
+        let mut ev = Evaluator::new();
+        let handle = ev.interrupt_handle();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            handle.store(true, Ordering::Relaxed);
+        });
+        // Shallow but exponential, so it runs until interrupted.
+        let src = "(define (spin n) (match n (0 0) (_ (+ (spin (- n 1)) (spin (- n 1))))))
+                   (guard (e (#t 'caught)) (spin 64))";
+        let err = ev.eval_str(src, "test", &mut env).unwrap_err();
+        interrupter.join().unwrap();
+        assert_eq!(err.root(), &ErrType::interrupted());
+        assert_eq!(ev.eval_str("(spin 3)", "test", &mut env)?, Val::Int(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_define() -> Result<(), ErrType> {
+        assert_eq!(eval("(define x 2) (define (f a . r) (list a x r)) (f 1 3)")?, eval("'(1 2 (3))")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_optional_defaults() -> Result<(), ErrType> {
+        let src = "(define y 10) (define (f a #!optional (n (+ 1 2)) (m (list a y)) k) (list n m k))";
+        assert_eq!(eval(&format!("{} (f 1)", src))?, eval("'(3 (1 10) #f)")?);
+        assert_eq!(eval(&format!("{} (f 1 2 3 4)", src))?, eval("'(2 3 4)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_lexical_scope() -> Result<(), ErrType> {
+        let src = "(define x 1) (define (f) x) (define (g x) (f)) (g 2)";
+        assert_eq!(eval(src)?, Val::Int(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_error_span() {
+        let err = eval("(define (f l) (car l))\n(+ 1 (f 2))").unwrap_err();
+        assert_eq!(err.untraced().to_string(), "test:1:15: Wrong type: expected list, got int");
+    }
+
+    #[test]
+    fn test_eval_backtrace() {
+        let src = "(define (f l) (car l))\n(define (g l) (list (f l)))\n(g 2)";
+        let err = eval(src).unwrap_err();
+        let expected = "Backtrace (innermost first):\n  car at test:1:15\n  f at test:2:21\n  g at test:3:1";
+        assert_eq!(err.backtrace().map(|b| b.to_string()), Some(String::from(expected)));
+        // A tail call replaces the caller's frame.
+        let src = "(define (f l) (car l))\n(define (g l) (f l))\n(g 2)";
+        let err = eval(src).unwrap_err();
+        let expected = "Backtrace (innermost first):\n  car at test:1:15\n  f at test:2:15";
+        assert_eq!(err.backtrace().map(|b| b.to_string()), Some(String::from(expected)));
+    }
+
+    #[test]
+    fn test_eval_unbound() {
+        let err = eval("(+ 1 y)").unwrap_err();
+        assert_eq!(err.to_string(), "test:1:6: y not found");
+    }
+
+    #[test]
+    fn test_guard_catches_type_error() -> Result<(), ErrType> {
+        let src = "(define (f l) (car l))
+                   (guard (e ((type-error? e) (error-object-message e))) (f 1))";
+        assert_eq!(eval(src)?, Val::string("Wrong type: expected list, got int"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_guard_raise() -> Result<(), ErrType> {
+        assert_eq!(eval("(guard (e ((symbol->string e) e)) (raise 'oops))")?, eval("'oops")?);
+        assert_eq!(eval("(guard (e (else 2)) (+ 1 (raise 1)))")?, Val::Int(2));
+        assert_eq!(eval("(guard (e (else 2)) 1)")?, Val::Int(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_guard_error_object() -> Result<(), ErrType> {
+        let src = "(guard (e ((error-object? e) (error-object-irritants e))) (error \"bad\" 1 2))";
+        assert_eq!(eval(src)?, eval("'(1 2)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_guard_rethrow() {
+        let err = eval("(guard (e ((type-error? e) 0)) (raise 1))").unwrap_err();
+        assert_eq!(err.root(), &ErrType::raise(Val::Int(1)));
+        let err = eval("(guard (e ((type-error? e) (raise e))) (car 1))").unwrap_err();
+        assert_eq!(err.root(), &ErrType::type_error("list", "int"));
+    }
+
+    const PARSE: &str = "(define (parse x) (+ x 0))
+                         (define (use-zero c) (invoke-restart 'use-value 0))
+                         (define (skip c) (invoke-restart 'skip))
+                         (define (decline c) c)";
+
+    #[test]
+    fn test_handler_use_value() -> Result<(), ErrType> {
+        let src = "(handler-bind ((type-error? use-zero)) (list (parse 1) (parse #\\a) (parse 3)))";
+        assert_eq!(eval(&format!("{} {}", PARSE, src))?, eval("'(1 0 3)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_handler_defined_locally() -> Result<(), ErrType> {
+        let src = "(define (first l) (car l))
+                   (define (safe-first l)
+                     (define (any c) #t)
+                     (define (zero c) (invoke-restart 'use-value 0))
+                     (handler-bind ((any zero)) (list (first l) (first '(1)))))
+                   (safe-first 5)";
+        assert_eq!(eval(src)?, eval("'(0 1)")?);
+        let src = "(module m (export first) (define (first l) (car l)))
+                   (import m)
+                   (define (any c) #t)
+                   (define (zero c) (invoke-restart 'use-value 0))
+                   (handler-bind ((any zero)) (first 5))";
+        assert_eq!(eval(src)?, Val::Int(0));
+        assert_eq!(eval("(handler-bind ((any zero)) 1)").unwrap_err().root(), &ErrType::lookup("any"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_handler_evaluator_errors() -> Result<(), ErrType> {
+        let defs = "(define (any c) #t)
+                    (define (z c) (invoke-restart 'use-value 0))
+                    (define-record-type point (make-point x) point? (x px))
+                    (define (f x) x) ";
+        let eval_with = |src: &str| eval(&format!("{}{}", defs, src));
+        assert_eq!(eval_with("(handler-bind ((any z)) (px 5))")?, Val::Int(0));
+        assert_eq!(eval_with("(handler-bind ((any z)) (list (f) (f 1)))")?, eval("'(0 1)")?);
+        assert_eq!(eval_with("(handler-bind ((any z)) (+ 1 y))")?, Val::Int(1));
+        assert_eq!(eval_with("(handler-bind ((any z)) (list (match 1 (2 'two))))")?, eval("'(0)")?);
+        assert_eq!(eval_with("(handler-bind ((any z)) (list (nope 1)))")?, eval("'(0)")?);
+        let err = eval_with("(list (f))").unwrap_err();
+        assert_eq!(err.root(), &ErrType::arity_mismatch("f", &[Arity::SomeArgs(1)], 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_handler_restart_case() -> Result<(), ErrType> {
+        let src = "(handler-bind ((type-error? skip))
+                     (list (restart-case (parse 1) (skip () 'skipped))
+                           (restart-case (parse #\\a) (skip () 'skipped))))";
+        assert_eq!(eval(&format!("{} {}", PARSE, src))?, eval("'(1 skipped)")?);
+        let src = "(define (use-one c) (invoke-restart 'use-one 1))
+                   (handler-bind ((error-object? use-one))
+                     (restart-case (car 1) (use-one (v) (list v v))))";
+        assert_eq!(eval(src)?, eval("'(1 1)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_handler_declines() -> Result<(), ErrType> {
+        let src = "(guard (e (else 'caught))
+                     (handler-bind ((type-error? use-zero))
+                       (handler-bind ((type-error? decline)) (parse #\\a))))";
+        assert_eq!(eval(&format!("{} {}", PARSE, src))?, Val::Int(0));
+        let src = "(guard (e (else 'caught)) (handler-bind ((type-error? decline)) (parse #\\a)))";
+        assert_eq!(eval(&format!("{} {}", PARSE, src))?, eval("'caught")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_handler_guard_does_not_catch_restarts() -> Result<(), ErrType> {
+        let src = "(handler-bind ((type-error? skip))
+                     (restart-case (guard (e (else 'caught)) (parse #\\a)) (skip () 'skipped)))";
+        assert_eq!(eval(&format!("{} {}", PARSE, src))?, eval("'skipped")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_primitives_are_bindings() -> Result<(), ErrType> {
+        assert_eq!(eval("(define (load x) (list x)) (load 5)")?, eval("'(5)")?);
+        assert_eq!(eval("(define (invoke-restart x) x) (invoke-restart 'mine)")?, eval("'mine")?);
+        let src = "(define restart invoke-restart)
+                   (handler-bind (((lambda (c) #t) (lambda (c) (restart 'use-value 0)))) (car 1))";
+        assert_eq!(eval(src)?, Val::Int(0));
+        #[cfg(feature = "io")]
+        {
+            assert_eq!(eval("(type-of load)")?, eval("'procedure")?);
+            let mut env = Env::new(Some(&core()));
+            let err = Evaluator::sandboxed(Limits::default()).eval_str("(load \"x.lisp\")", "test", &mut env).unwrap_err();
+            assert_eq!(err.root(), &ErrType::lookup("load"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_invoke_unknown_restart() {
+        let err = eval("(invoke-restart 'nope)").unwrap_err();
+        assert_eq!(err.root(), &ErrType::lookup("nope"));
+    }
+
+    #[test]
+    fn test_call_cc_escape() -> Result<(), ErrType> {
+        let src = "(define (f k) (+ 1 (k 42)))
+                   (define (g k) 7)
+                   (list (call/cc f) (call/cc g))";
+        assert_eq!(eval(src)?, eval("'(42 7)")?);
+        let src = "(define (outer k) (inner k) 1)
+                   (define (inner k) (k 2))
+                   (call-with-current-continuation outer)";
+        assert_eq!(eval(src)?, Val::Int(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_call_cc_not_caught_by_guard() -> Result<(), ErrType> {
+        let src = "(define (f k) (guard (e (else 'caught)) (k 1)))
+                   (call/cc f)";
+        assert_eq!(eval(src)?, Val::Int(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_call_cc_reentry() -> Result<(), ErrType> {
+        // Each call of the saved continuation defines r again and runs the
+        // forms after it, until the count reaches 3.
+        let src = "(define saved (vector #f 0))
+                   (define (save k) (vector-set! saved 0 k) 0)
+                   (define r (+ 100 (call/cc save)))
+                   (vector-set! saved 1 (+ (vector-ref saved 1) 1))
+                   (match (vector-ref saved 1) (3 #f) (n ((vector-ref saved 0) n)))
+                   (list r (vector-ref saved 1))";
+        assert_eq!(eval(src)?, eval("'(102 3)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_call_cc_expression() -> Result<(), ErrType> {
+        let src = "(define (f k) (k 5))
+                   (+ 1 (call/cc (car (list f))))";
+        assert_eq!(eval(src)?, Val::Int(6));
+        let src = "(define (f) 1) (define (g) 2) (dynamic-wind f (car (list g)) f)";
+        assert_eq!(eval(src)?, Val::Int(2));
+        Ok(())
+    }
+
+    thread_local! {
+        static LOG: RefCell<Vec<Val>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn log(args: Args) -> Res {
+        LOG.with(|l| l.borrow_mut().push(args[0].clone()));
+        Ok(args[0].clone())
+    }
+
+    #[test]
+    fn test_dynamic_wind_order() -> Result<(), ErrType> {
+        let core = core();
+        core.register_func("log", Arity::SomeArgs(1), log);
+        let mut env = Env::new(Some(&core));
+        let src = "(define (before) (log 'before))
+                   (define (after) (log 'after))
+                   (define (f k)
+                     (define (thunk) (log 'during) (k 1) (log 'unreachable))
+                     (dynamic-wind before thunk after))
+                   (log (call/cc f))";
+        Evaluator::new().eval_str(src, "test", &mut env)?;
+        let expected: Vec<Val> = ["before", "during", "after"].iter()
+            .map(|s| Val::Symbol(Symbol::intern(s)))
+            .chain(Some(Val::Int(1)))
+            .collect();
+        assert_eq!(LOG.with(|l| l.borrow().clone()), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dynamic_wind_reentry() -> Result<(), ErrType> {
+        let core = core();
+        core.register_func("log", Arity::SomeArgs(1), log);
+        let mut env = Env::new(Some(&core));
+        let src = "(define saved (vector #f 0))
+                   (define (before) (log 'before))
+                   (define (after) (log 'after))
+                   (define (save k) (vector-set! saved 0 k) 0)
+                   (define (thunk) (log (call/cc save)))
+                   (dynamic-wind before thunk after)
+                   (vector-set! saved 1 (+ (vector-ref saved 1) 1))
+                   (match (vector-ref saved 1) (1 ((vector-ref saved 0) 'again)) (_ #f))";
+        Evaluator::new().eval_str(src, "test", &mut env)?;
+        let expected = eval("'(before 0 after before again after)")?;
+        assert_eq!(Val::List(LOG.with(|l| l.borrow().clone()).into()), expected);
+        Ok(())
+    }
+    #[test]
+    fn test_shift_reset() -> Result<(), ErrType> {
+        assert_eq!(eval("(+ 1 (reset (+ 10 (shift k (k (k 1))))))")?, Val::Int(22));
+        assert_eq!(eval("(reset (list 1 (shift k 5)))")?, Val::Int(5));
+        assert_eq!(eval("(reset 1 2)")?, Val::Int(2));
+        let src = "(define k (reset (list 1 (shift k k))))
+                   (list (k 2) (k 3))";
+        assert_eq!(eval(src)?, eval("'((1 2) (1 3))")?);
+        // Each shift captures up to the innermost reset only.
+        let src = "(reset (list 1 (reset (list 2 (shift k (k 3))))))";
+        assert_eq!(eval(src)?, eval("'(1 (2 3))")?);
+        let src = "(define (f x) (shift k (list x (k x))))
+                   (reset (+ 1 (f 2)))";
+        assert_eq!(eval(src)?, eval("'(2 3)")?);
+        let err = eval("(list (shift k 1))").unwrap_err();
+        assert_eq!(err.root(), &ErrType::user("shift outside of reset", vec![]));
+        assert_eq!(err.span().map(|s| (s.line, s.col)), Some((1, 7)));
+        assert!(eval("(reset (shift 1 2))").is_err());
+        Ok(())
+    }
 
-pub fn eval_fn(env: &Env, f: &dyn Fn) -> F {
-    
+    #[test]
+    fn test_shift_dynamic_wind() -> Result<(), ErrType> {
+        let core = core();
+        core.register_func("log", Arity::SomeArgs(1), log);
+        let mut env = Env::new(Some(&core));
+        let src = "(define (before) (log 'before))
+                   (define (after) (log 'after))
+                   (define k (reset (dynamic-wind before (lambda () (log (shift k k))) after)))
+                   (k 1)
+                   (k 2)";
+        Evaluator::new().eval_str(src, "test", &mut env)?;
+        let expected = eval("'(before after before 1 after before 2 after)")?;
+        assert_eq!(Val::List(LOG.with(|l| l.borrow().clone()).into()), expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+//! A collector for the cycles reference counting can't free. Every
+//! environment, procedure, vector and record is tracked as it's made, and a
+//! collection works out which of them are kept alive only by each other, by
+//! trial deletion: an object referred to more times than the other objects
+//! account for is held from outside them, by the evaluator's stack, a
+//! continuation or Rust code, so it and everything it refers to are live.
+//! The rest are garbage, and emptying their scopes, elements and fields
+//! breaks their cycles.
+//!
+//! References are counted through lists, maps, persistent vectors and sets
+//! only where no other value shares their storage, and never through
+//! continuations, so what those hold counts as held from outside. A cycle
+//! passing through them is kept, but nothing live is ever freed.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::env::{Env, Procedure, Scope, WeakEnv};
+use crate::types::{Record, Val};
+
+/// How many objects may be tracked before the first automatic collection.
+/// Later ones run once twice as many are tracked as survived the last.
+const MIN_THRESHOLD: usize = 10_000;
+
+/// An object the collector knows of, without keeping it alive.
+pub enum Tracked {
+    Env(WeakEnv),
+    Procedure(Weak<Procedure>),
+    Vector(Weak<RefCell<Vec<Val>>>),
+    Record(Weak<Record>),
+}
+
+/// A tracked object still alive, held while collecting.
+enum Object {
+    Env(Env),
+    Procedure(Rc<Procedure>),
+    Vector(Rc<RefCell<Vec<Val>>>),
+    Record(Rc<Record>),
+}
+
+/// What the collector has done on this thread.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    /// The tracked objects alive now.
+    pub live: usize,
+    /// The collections run, whether by `collect` or automatically.
+    pub collections: usize,
+    /// The objects collections have freed.
+    pub freed: usize,
+}
+
+struct Heap {
+    tracked: Vec<Tracked>,
+    /// How many objects may be tracked before collecting again.
+    threshold: usize,
+    collections: usize,
+    freed: usize,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = const {
+        RefCell::new(Heap { tracked: Vec::new(), threshold: MIN_THRESHOLD, collections: 0, freed: 0 })
+    };
+}
+
+impl Tracked {
+    fn upgrade(&self) -> Option<Object> {
+        match self {
+            Tracked::Env(e) => e.upgrade().map(Object::Env),
+            Tracked::Procedure(p) => p.upgrade().map(Object::Procedure),
+            Tracked::Vector(v) => v.upgrade().map(Object::Vector),
+            Tracked::Record(r) => r.upgrade().map(Object::Record),
+        }
+    }
+    fn is_live(&self) -> bool {
+        match self {
+            Tracked::Env(e) => e.is_live(),
+            Tracked::Procedure(p) => p.strong_count() > 0,
+            Tracked::Vector(v) => v.strong_count() > 0,
+            Tracked::Record(r) => r.strong_count() > 0,
+        }
+    }
+}
+
+impl Object {
+    fn id(&self) -> *const () {
+        match self {
+            Object::Env(e) => e.as_ptr(),
+            Object::Procedure(p) => Rc::as_ptr(p).cast(),
+            Object::Vector(v) => Rc::as_ptr(v).cast(),
+            Object::Record(r) => Rc::as_ptr(r).cast(),
+        }
+    }
+    fn strong_count(&self) -> usize {
+        match self {
+            Object::Env(e) => e.strong_count(),
+            Object::Procedure(p) => Rc::strong_count(p),
+            Object::Vector(v) => Rc::strong_count(v),
+            Object::Record(r) => Rc::strong_count(r),
+        }
+    }
+    fn downgrade(&self) -> Tracked {
+        match self {
+            Object::Env(e) => Tracked::Env(e.downgrade()),
+            Object::Procedure(p) => Tracked::Procedure(Rc::downgrade(p)),
+            Object::Vector(v) => Tracked::Vector(Rc::downgrade(v)),
+            Object::Record(r) => Tracked::Record(Rc::downgrade(r)),
+        }
+    }
+    /// Adds the ids of the objects this one refers to to `out`, once per
+    /// reference, or returns false if it's being changed and can't be looked
+    /// inside.
+    fn refs(&self, out: &mut Vec<*const ()>) -> bool {
+        match self {
+            Object::Env(e) => {
+                let Some(scope) = e.try_scope() else {
+                    return false;
+                };
+                out.extend(e.parent().map(Env::as_ptr));
+                refs_in(scope.values(), out);
+            }
+            Object::Procedure(p) => out.push(p.env.as_ptr()),
+            Object::Vector(v) => match v.try_borrow() {
+                Ok(items) => refs_in(items.iter(), out),
+                Err(_) => return false,
+            },
+            Object::Record(r) => match r.fields.try_borrow() {
+                Ok(fields) => refs_in(fields.iter(), out),
+                Err(_) => return false,
+            },
+        }
+        true
+    }
+    /// Empties this object, if it's an environment, vector or record,
+    /// moving what it held to `scopes` or `vals`.
+    fn clear(&self, scopes: &mut Vec<Scope>, vals: &mut Vec<Val>) {
+        match self {
+            Object::Env(e) => scopes.extend(e.take_scope()),
+            Object::Procedure(_) => (),
+            Object::Vector(v) => vals.extend(v.try_borrow_mut().iter_mut().flat_map(|v| v.drain(..))),
+            Object::Record(r) => vals.extend(r.fields.try_borrow_mut().iter_mut().flat_map(|f| f.drain(..))),
+        }
+    }
+}
+
+/// Adds the ids of the objects `vals` refer to to `out`, looking inside the
+/// values that hold others where no other value shares their storage.
+fn refs_in<'a>(vals: impl Iterator<Item = &'a Val>, out: &mut Vec<*const ()>) {
+    let mut stack: Vec<&Val> = vals.collect();
+    while let Some(v) = stack.pop() {
+        match v {
+            Val::Procedure(p) => out.push(Rc::as_ptr(p).cast()),
+            Val::Vector(v) => out.push(Rc::as_ptr(v).cast()),
+            Val::Record(r) => out.push(Rc::as_ptr(r).cast()),
+            Val::List(l) => stack.extend(l.unshared()),
+            Val::PVector(a) => stack.extend(a.unshared()),
+            Val::Set(a) => stack.extend(a.unshared()),
+            Val::Map(a) => stack.extend(a.unshared().into_iter().flat_map(|(k, v)| [k, v])),
+            _ => (),
+        }
+    }
+}
+
+/// Starts tracking an object just made, collecting first if enough have
+/// been made since the last collection.
+pub fn track(t: Tracked) {
+    let due = HEAP.with(|h| {
+        let mut h = h.borrow_mut();
+        h.tracked.push(t);
+        h.tracked.len() >= h.threshold
+    });
+    if due {
+        collect();
+    }
+}
+
+/// Frees the tracked objects only other tracked objects keep alive, and
+/// returns how many there were.
+pub fn collect() -> usize {
+    let objects: Vec<Object> = HEAP.with(|h| h.borrow_mut().tracked.drain(..).filter_map(|t| t.upgrade()).collect());
+    let index: HashMap<*const (), usize> = objects.iter().enumerate().map(|(i, o)| (o.id(), i)).collect();
+    let edges: Vec<Option<Vec<usize>>> = objects.iter().map(|o| {
+        let mut ids = Vec::new();
+        o.refs(&mut ids).then(|| ids.iter().filter_map(|id| index.get(id).copied()).collect())
+    }).collect();
+    let mut internal = vec![0; objects.len()];
+    for &i in edges.iter().flatten().flatten() {
+        internal[i] += 1;
+    }
+    // Besides the references other objects account for, each object has
+    // one from `objects`.
+    let mut stack: Vec<usize> = (0..objects.len())
+        .filter(|&i| edges[i].is_none() || objects[i].strong_count() > internal[i] + 1)
+        .collect();
+    let mut live = vec![false; objects.len()];
+    while let Some(i) = stack.pop() {
+        if !live[i] {
+            live[i] = true;
+            stack.extend(edges[i].iter().flatten().filter(|&&j| !live[j]));
+        }
+    }
+    // What garbage held is dropped last, once nothing refers to it.
+    let (mut scopes, mut vals) = (Vec::new(), Vec::new());
+    for (o, _) in objects.iter().zip(&live).filter(|(_, &l)| !l) {
+        o.clear(&mut scopes, &mut vals);
+    }
+    let freed = live.iter().filter(|&&l| !l).count();
+    HEAP.with(|h| {
+        let mut h = h.borrow_mut();
+        let survivors = objects.iter().zip(&live).filter(|(_, &l)| l).map(|(o, _)| o.downgrade());
+        h.tracked.extend(survivors);
+        h.threshold = MIN_THRESHOLD.max(2 * h.tracked.len());
+        h.collections += 1;
+        h.freed += freed;
+    });
+    drop(objects);
+    drop(scopes);
+    drop(vals);
+    freed
+}
+
+pub fn stats() -> Stats {
+    HEAP.with(|h| {
+        let h = h.borrow();
+        Stats { live: h.tracked.iter().filter(|t| t.is_live()).count(), collections: h.collections, freed: h.freed }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::env::Env;
+    use crate::errors::ErrType;
+    use crate::eval::Evaluator;
+    use crate::gc::*;
+    use crate::stdlib::core::core;
+    use crate::types::Val;
+
+    #[test]
+    fn test_vector_cycle() -> Result<(), ErrType> {
+        let v = Val::vector(vec![]);
+        let inner = Val::List(vec![v.clone()].into());
+        v.unwrap_vector()?.borrow_mut().push(inner);
+        let Val::Vector(rc) = &v else { unreachable!() };
+        let weak = Rc::downgrade(rc);
+        assert_eq!(collect(), 0);
+        drop(v);
+        assert!(weak.upgrade().is_some());
+        assert_eq!(collect(), 1);
+        assert!(weak.upgrade().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_list_keeps_cycle() -> Result<(), ErrType> {
+        // The list is held from outside as well as by the vector, so what
+        // it refers to is live.
+        let v = Val::vector(vec![]);
+        let shared = Val::List(vec![v.clone()].into());
+        v.unwrap_vector()?.borrow_mut().push(shared.clone());
+        drop(v);
+        assert_eq!(collect(), 0);
+        assert_eq!(shared.to_string(), "(#((#<vector ...>)))");
+        Ok(())
+    }
+
+    #[test]
+    fn test_closure_cycle() -> Result<(), ErrType> {
+        let core = core();
+        let mut env = Env::new(Some(&core));
+        let mut ev = Evaluator::new();
+        // Each call's environment binds g to a procedure holding on to it.
+        let src = "(define (make) (define (f) 1) (define g f) 0)
+                   (make) (make) (make)
+                   (gc)";
+        assert_eq!(ev.eval_str(src, "test", &mut env)?, Val::Int(6));
+        assert_eq!(ev.eval_str("(gc)", "test", &mut env)?, Val::Int(0));
+        assert_eq!(ev.eval_str("(make)", "test", &mut env)?, Val::Int(0));
+        assert_eq!(stats().freed, 6);
+        let gc_stats = ev.eval_str("(gc-stats)", "test", &mut env)?;
+        assert_eq!(gc_stats.unwrap_map()?.get(&ev.eval_str("'collections", "test", &mut env)?), Some(&Val::Int(2)));
+        Ok(())
+    }
+}
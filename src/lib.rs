@@ -1,4 +1,12 @@
+pub mod diagnostic;
 pub mod errors;
+pub mod symbol;
 pub mod types;
 pub mod env;
+pub mod eval;
+pub mod gc;
+pub mod module;
+pub mod pattern;
+pub mod persistent;
+pub mod reader;
 pub mod stdlib;
@@ -1,38 +1,144 @@
-use lisp::errors::ErrType;
-use lisp::env::*;
-use lisp::stdlib::core::core;
-use lisp::types::*;
+use std::env::args;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
 
+use lisp::diagnostic::render;
+use lisp::env::Env;
+use lisp::eval::Evaluator;
+use lisp::module::env_search_path;
+use lisp::stdlib::core::core;
+use lisp::types::Val;
 
-fn test() -> Res {
-    let core = core();
-    let mut p = Env::new(Some(&core));
-    let a = Val::Int(1);
-    let b = Val::Int(2);
-    p.register("a", a);
-    p.register("b", b);
-    /* (defn f (c) (let (a 1 b 2) (+ a b c))) */
-    let f = |args: Args| {
-        let name = "+";
-        let fu = p.lookup(name)?;
-        let fu = fu.unwrap_func().ok_or(ErrType::not_a_function(name))?;
-        let fu = fu.lookup(Arity::VarArgs).ok_or(ErrType::lookup(name))?;
-        let a = p.lookup("a").and_then(|v| v.unwrap_val().ok_or(ErrType::lookup("a")))?;
-        let b = p.lookup("b").and_then(|v| v.unwrap_val().ok_or(ErrType::lookup("b")))?;
-        let c = args[0];
-        Ok(fu(vec![*a, *b, c]))
-    };
-    let func_name = "f";
-    p.register_func(func_name, Arity::SomeArgs(1), f);
-    /* (f 3) */
-    let v = Val::Int(3);
-    let func = p.lookup(func_name)?;
-    let func = func.unwrap_func().ok_or(ErrType::lookup(func_name))?;
+const USAGE: &str = "usage: lisp [-L dir]... [file]";
 
+/// Parses `-L dir` (or `--path dir`) options, which come before directories
+/// from `LISP_PATH` and the working directory in the search path, and an
+/// optional file to run.
+fn parse_args() -> (Vec<PathBuf>, Option<String>) {
+    let mut search_path = Vec::new();
+    let mut file = None;
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-L" | "--path" => match args.next() {
+                Some(dir) => search_path.push(PathBuf::from(dir)),
+                None => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            _ if file.is_none() => file = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        }
+    }
+    search_path.extend(env_search_path());
+    search_path.push(PathBuf::from("."));
+    (search_path, file)
+}
 
+/// The interrupt handle of the REPL's evaluator, for the SIGINT handler.
+static INTERRUPT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
 
+/// Makes Ctrl-C interrupt the form being evaluated rather than exit. Without
+/// a dependency for signals, this calls the C library's `signal` directly.
+#[cfg(unix)]
+fn handle_ctrl_c(interrupt: Arc<AtomicBool>) {
+    const SIGINT: i32 = 2;
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+    extern "C" fn on_sigint(_: i32) {
+        if let Some(interrupt) = INTERRUPT.get() {
+            interrupt.store(true, Ordering::Relaxed);
+        }
+    }
+    INTERRUPT.get_or_init(|| interrupt);
+    // Safe: the handler only stores to an atomic.
+    unsafe {
+        signal(SIGINT, on_sigint);
+    }
 }
 
+#[cfg(not(unix))]
+fn handle_ctrl_c(_: Arc<AtomicBool>) {}
+
 fn main() {
-    println!("Hello, world!");
+    let (search_path, file) = parse_args();
+    let core = core();
+    let mut env = Env::new(Some(&core));
+    let mut ev = Evaluator::new();
+    #[cfg(feature = "io")]
+    ev.set_search_path(search_path);
+    #[cfg(not(feature = "io"))]
+    let _ = search_path;
+
+    if let Some(path) = file {
+        let src = fs::read_to_string(&path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        });
+        if let Err(e) = ev.eval_str(&src, &path, &mut env) {
+            eprintln!("{}", render(&e, &path, &src));
+            process::exit(1);
+        }
+        return;
+    }
+
+    let interrupt = ev.interrupt_handle();
+    handle_ctrl_c(Arc::clone(&interrupt));
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    for n in 1.. {
+        print!("> ");
+        io::stdout().flush().ok();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        let file = format!("<repl:{}>", n);
+        // Forget a Ctrl-C pressed at the prompt.
+        interrupt.store(false, Ordering::Relaxed);
+        match ev.eval_str(&line, &file, &mut env) {
+            Ok(Val::Nil) => (),
+            Ok(v) => println!("{}", v),
+            Err(e) => eprintln!("{}", render(&e, &file, &line)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lisp::env::*;
+    use lisp::errors::ErrType;
+    use lisp::stdlib::core::core;
+    use lisp::types::*;
+
+    /// Calls a builtin by hand, as a procedure `(defn f (c) (+ a b c))`
+    /// would, with `a` and `b` bound in the environment.
+    #[test]
+    fn test() -> Result<(), ErrType> {
+        let core = core();
+        let p = Env::new(Some(&core));
+        p.register("a", Val::Int(1));
+        p.register("b", Val::Int(2));
+        let f = |args: Args| {
+            let name = "+";
+            let fu = p.lookup(name)?;
+            let fu = fu.unwrap_func().ok_or_else(|| ErrType::not_a_function(name))?;
+            let a = p.lookup("a")?.unwrap_val().cloned().ok_or_else(|| ErrType::lookup("a"))?;
+            let b = p.lookup("b")?.unwrap_val().cloned().ok_or_else(|| ErrType::lookup("b"))?;
+            let c = args[0].clone();
+            fu.call(name, vec![a, b, c])
+        };
+        /* (f 3) */
+        assert_eq!(f(vec![Val::Int(3)])?, Val::Int(6));
+        Ok(())
+    }
 }
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::env;
+#[cfg(feature = "io")]
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::env::Env;
+use super::errors::ErrType;
+use super::symbol::Symbol;
+use super::types::Val;
+
+/// A module's definitions, kept apart from the environment that imports it,
+/// and the names it exports.
+pub struct Module {
+    pub env: Env,
+    pub exports: Vec<Symbol>,
+}
+
+pub type Modules = HashMap<Symbol, Module>;
+
+/// The environment variable listing extra directories to search, separated
+/// as in `PATH`.
+pub const PATH_VAR: &str = "LISP_PATH";
+
+const EXTENSION: &str = "lisp";
+
+/// The directories listed in `LISP_PATH`.
+pub fn env_search_path() -> Vec<PathBuf> {
+    env::var_os(PATH_VAR).map_or_else(Vec::new, |p| env::split_paths(&p).collect())
+}
+
+/// The file defining module `name`: `lib/name` is `lib/name.lisp`, and
+/// `lib/name.v2` is `lib/name.v2.lisp`.
+pub fn module_file(name: Symbol) -> PathBuf {
+    PathBuf::from(format!("{}.{}", name, EXTENSION))
+}
+
+/// Checks that module `name` names a file under the search path: its
+/// `/`-separated components are non-empty and aren't `.` or `..`, and it has
+/// no other path separators.
+fn check_module_name(name: Symbol) -> Result<Symbol, ErrType> {
+    let bad = |c: &str| c.is_empty() || c == "." || c == ".." || c.contains('\\');
+    if name.as_str().split('/').any(bad) {
+        return Err(ErrType::syntax(&format!("invalid module name {}", name)));
+    }
+    Ok(name)
+}
+
+/// Finds `file` in the first of `dirs` containing it; an empty search path
+/// means the working directory. Absolute paths aren't searched for, and one
+/// that doesn't exist is reported as is.
+#[cfg(feature = "io")]
+pub fn find_file(file: &Path, dirs: &[PathBuf]) -> Result<PathBuf, ErrType> {
+    let cwd = [PathBuf::from(".")];
+    let dirs = if dirs.is_empty() { &cwd[..] } else { dirs };
+    if file.is_absolute() {
+        return if file.is_file() {
+            Ok(file.to_path_buf())
+        } else {
+            Err(ErrType::file_not_found(&file.to_string_lossy(), &[]))
+        };
+    }
+    match dirs.iter().map(|d| d.join(file)).find(|p| p.is_file()) {
+        Some(p) => Ok(p),
+        None => {
+            let searched: Vec<_> = dirs.iter().map(|d| d.to_string_lossy()).collect();
+            let searched: Vec<&str> = searched.iter().map(|d| &**d).collect();
+            Err(ErrType::file_not_found(&file.to_string_lossy(), &searched))
+        }
+    }
+}
+
+/// An import set, as in R7RS: a module name, or `(only set name...)`,
+/// `(except set name...)`, `(rename set (from to)...)` or `(prefix set p)`
+/// applied to another import set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportSet {
+    Module(Symbol),
+    Only(Box<ImportSet>, Vec<Symbol>),
+    Except(Box<ImportSet>, Vec<Symbol>),
+    Rename(Box<ImportSet>, Vec<(Symbol, Symbol)>),
+    Prefix(Box<ImportSet>, String),
+}
+
+/// An imported name: the name it's bound to, the module, and the name the
+/// module exports it as.
+pub type Import = (Symbol, Symbol, Symbol);
+
+/// Checks that every one of `names` is among `imports`.
+fn check_imported(imports: &[Import], names: &[Symbol]) -> Result<(), ErrType> {
+    match names.iter().find(|n| !imports.iter().any(|(local, ..)| local == *n)) {
+        Some(missing) => Err(ErrType::lookup(missing.as_str())),
+        None => Ok(()),
+    }
+}
+
+impl ImportSet {
+    pub fn parse(v: &Val) -> Result<ImportSet, ErrType> {
+        let l = match v {
+            Val::Symbol(s) => return Ok(ImportSet::Module(check_module_name(*s)?)),
+            v => v.unwrap_list()?,
+        };
+        let malformed = || ErrType::syntax("malformed import set");
+        let (head, set, args) = match &l[..] {
+            [head, set, args @ ..] => (head.unwrap_symbol()?.as_str(), Box::new(ImportSet::parse(set)?), args),
+            _ => return Err(malformed()),
+        };
+        let names = || args.iter().map(|a| a.unwrap_symbol().copied()).collect::<Result<Vec<_>, _>>();
+        match head {
+            "only" => Ok(ImportSet::Only(set, names()?)),
+            "except" => Ok(ImportSet::Except(set, names()?)),
+            "rename" => {
+                let pairs = args.iter()
+                    .map(|a| match &**a.unwrap_list()? {
+                        [from, to] => Ok((*from.unwrap_symbol()?, *to.unwrap_symbol()?)),
+                        _ => Err(malformed()),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(ImportSet::Rename(set, pairs))
+            }
+            "prefix" => match args {
+                [p] => Ok(ImportSet::Prefix(set, p.unwrap_symbol()?.to_string())),
+                _ => Err(malformed()),
+            },
+            _ => Err(malformed()),
+        }
+    }
+
+    /// The module this set imports from.
+    pub fn module(&self) -> Symbol {
+        match self {
+            ImportSet::Module(m) => *m,
+            ImportSet::Only(set, _) | ImportSet::Except(set, _) => set.module(),
+            ImportSet::Rename(set, _) | ImportSet::Prefix(set, _) => set.module(),
+        }
+    }
+
+    /// Lists the names this set imports, given the loaded modules.
+    pub fn resolve(&self, modules: &Modules) -> Result<Vec<Import>, ErrType> {
+        match self {
+            ImportSet::Module(m) => {
+                let module = modules.get(m).ok_or_else(|| ErrType::lookup(m.as_str()))?;
+                Ok(module.exports.iter().map(|e| (*e, *m, *e)).collect())
+            }
+            ImportSet::Only(set, names) => {
+                let imports = set.resolve(modules)?;
+                check_imported(&imports, names)?;
+                Ok(imports.into_iter().filter(|(local, ..)| names.contains(local)).collect())
+            }
+            ImportSet::Except(set, names) => {
+                let imports = set.resolve(modules)?;
+                check_imported(&imports, names)?;
+                Ok(imports.into_iter().filter(|(local, ..)| !names.contains(local)).collect())
+            }
+            ImportSet::Rename(set, pairs) => {
+                let mut imports = set.resolve(modules)?;
+                check_imported(&imports, &pairs.iter().map(|(from, _)| *from).collect::<Vec<_>>())?;
+                for (local, ..) in imports.iter_mut() {
+                    if let Some((_, to)) = pairs.iter().find(|(from, _)| from == local) {
+                        *local = *to;
+                    }
+                }
+                Ok(imports)
+            }
+            ImportSet::Prefix(set, prefix) => {
+                let imports = set.resolve(modules)?;
+                Ok(imports.into_iter()
+                    .map(|(local, m, e)| (Symbol::intern(&format!("{}{}", prefix, local)), m, e))
+                    .collect())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::read;
+
+    fn import_set(src: &str) -> Result<ImportSet, ErrType> {
+        ImportSet::parse(&read(src, "test")?[0].to_val())
+    }
+
+    fn modules() -> Modules {
+        let exports = ["a", "b", "c"].iter().map(|s| Symbol::intern(s)).collect();
+        let mut m = HashMap::new();
+        m.insert(Symbol::intern("m"), Module { env: Env::new(None), exports });
+        m
+    }
+
+    fn local_names(src: &str) -> Result<Vec<String>, ErrType> {
+        let imports = import_set(src)?.resolve(&modules())?;
+        Ok(imports.iter().map(|(local, ..)| local.to_string()).collect())
+    }
+
+    #[test]
+    fn test_resolve() -> Result<(), ErrType> {
+        assert_eq!(local_names("m")?, ["a", "b", "c"]);
+        assert_eq!(local_names("(only m c a)")?, ["a", "c"]);
+        assert_eq!(local_names("(except m a)")?, ["b", "c"]);
+        assert_eq!(local_names("(prefix (rename (only m a b) (a x)) m:)")?, ["m:x", "m:b"]);
+        assert_eq!(import_set("(prefix (only m a) m:)")?.module(), Symbol::intern("m"));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "io")]
+    fn test_find_file() -> Result<(), ErrType> {
+        let dir = env::temp_dir().join(format!("lisp-find-file-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("lib/m.lisp"), "").unwrap();
+        std::fs::write(dir.join("lib/m.v2.lisp"), "").unwrap();
+        let dirs = [PathBuf::from("/nonexistent"), dir.clone()];
+        let file = module_file(Symbol::intern("lib/m"));
+        assert_eq!(find_file(&file, &dirs)?, dir.join("lib/m.lisp"));
+        let file = module_file(Symbol::intern("lib/m.v2"));
+        assert_eq!(find_file(&file, &dirs)?, dir.join("lib/m.v2.lisp"));
+        let err = find_file(Path::new("lib/n.lisp"), &dirs[..1]).unwrap_err();
+        assert_eq!(err.to_string(), "lib/n.lisp not found in /nonexistent");
+        let err = find_file(Path::new("/nonexistent/n.lisp"), &dirs).unwrap_err();
+        assert_eq!(err.to_string(), "/nonexistent/n.lisp not found");
+        std::fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        assert_eq!(local_names("(only m d)"), Err(ErrType::lookup("d")));
+        assert_eq!(local_names("n"), Err(ErrType::lookup("n")));
+        assert!(import_set("(prefix m)").is_err());
+        assert!(import_set("(include m)").is_err());
+    }
+
+    #[test]
+    fn test_module_names() {
+        assert!(import_set("lib/m.v2").is_ok());
+        for name in ["../m", "lib/../../m", "/etc/m", "lib//m", "lib/", "./m", "lib\\m", "(only .. a)"] {
+            assert!(matches!(import_set(name), Err(ErrType::SyntaxError(_))), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::mem;
+use std::rc::{Rc, Weak};
+
+use super::errors::ErrType;
+use super::reader::{Form, FormKind};
+use super::symbol::Symbol;
+use super::types::Val;
+
+/// A pattern for `match`, parsed from its form:
+///
+/// - `_` matches anything, and any other symbol matches anything and binds it;
+/// - `'datum`, and atoms other than symbols, match values equal to them;
+/// - `(p q)` matches a list element by element, and `(p q . rest)`
+///   binds what's left of the list to `rest`;
+/// - `p ...` inside a list matches zero or more elements, binding each
+///   variable in `p` to the list of its matches;
+/// - `(? pred)` matches values the procedure `pred` evaluates to accepts, and
+///   `(? pred p)` must also match `p`.
+///
+/// A pattern is kept as a flat list of nodes, children before their parent,
+/// so it's cheap to clone and a `Matcher` can refer to its parts by index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    nodes: Rc<[Node]>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Node {
+    Wildcard,
+    Bind(Symbol),
+    Literal(Val),
+    List {
+        items: Vec<usize>,
+        repeat: Option<Repeat>,
+        rest: Option<usize>,
+    },
+    Pred(Form, Option<usize>),
+}
+
+/// The pattern followed by `...` in a list.
+#[derive(Debug, PartialEq, Eq)]
+struct Repeat {
+    /// Its index in the list's items.
+    index: usize,
+    /// The variables it binds, each once.
+    vars: Vec<Symbol>,
+    /// The number of bindings one match of it makes.
+    width: usize,
+}
+
+pub type Bindings = Vec<(Symbol, Val)>;
+
+impl Pattern {
+    const WILDCARD: &'static str = "_";
+    const ELLIPSIS: &'static str = "...";
+    const REST: &'static str = ".";
+    const PRED: &'static str = "?";
+    const QUOTE: &'static str = "quote";
+
+    pub fn parse(form: &Form) -> Result<Pattern, ErrType> {
+        let mut nodes = Vec::new();
+        Pattern::parse_node(form, &mut nodes)?;
+        Ok(Pattern { nodes: nodes.into() })
+    }
+
+    /// A pattern binding anything to `name`.
+    pub fn bind(name: Symbol) -> Pattern {
+        Pattern { nodes: Rc::new([Node::Bind(name)]) }
+    }
+
+    /// Parses `form` onto `nodes`, returning the index of its node.
+    fn parse_node(form: &Form, nodes: &mut Vec<Node>) -> Result<usize, ErrType> {
+        let node = match &form.kind {
+            FormKind::Atom(Val::Symbol(s)) if s.as_str() == Pattern::WILDCARD => Node::Wildcard,
+            FormKind::Atom(Val::Symbol(s)) => Node::Bind(*s),
+            FormKind::List(l) => Pattern::parse_list(l, nodes)?,
+            _ => Node::Literal(form.to_val()),
+        };
+        nodes.push(node);
+        Ok(nodes.len() - 1)
+    }
+
+    fn parse_list(l: &[Form], nodes: &mut Vec<Node>) -> Result<Node, ErrType> {
+        let symbol = |f: &Form| match &f.kind {
+            FormKind::Atom(Val::Symbol(s)) => Some(s.as_str()),
+            _ => None,
+        };
+        match (l.first().and_then(symbol), l) {
+            (Some(Pattern::QUOTE), [_, datum]) => return Ok(Node::Literal(datum.to_val())),
+            (Some(Pattern::PRED), [_, pred]) => return Ok(Node::Pred(pred.clone(), None)),
+            (Some(Pattern::PRED), [_, pred, p]) => {
+                return Ok(Node::Pred(pred.clone(), Some(Pattern::parse_node(p, nodes)?)));
+            }
+            (Some(Pattern::PRED), _) => return Err(ErrType::syntax("malformed (? pred) pattern")),
+            _ => (),
+        }
+        let mut items = Vec::with_capacity(l.len());
+        let mut repeat = None;
+        let mut rest = None;
+        let mut iter = l.iter();
+        while let Some(f) = iter.next() {
+            match symbol(f) {
+                Some(Pattern::ELLIPSIS) => {
+                    let last = match items.last() {
+                        Some(last) if repeat.is_none() => *last,
+                        _ => return Err(ErrType::syntax("... must follow a pattern, once per list")),
+                    };
+                    let mut binders = Vec::new();
+                    Pattern::collect_binders(nodes, last, &mut binders);
+                    let width = binders.len();
+                    let mut vars = Vec::with_capacity(width);
+                    for b in binders {
+                        if !vars.contains(&b) {
+                            vars.push(b);
+                        }
+                    }
+                    repeat = Some(Repeat { index: items.len() - 1, vars, width });
+                }
+                Some(Pattern::REST) => {
+                    let p = iter.next().ok_or_else(|| ErrType::syntax("missing pattern after ."))?;
+                    if iter.next().is_some() || repeat.is_some() {
+                        return Err(ErrType::syntax("the pattern after . must end a list without ..."));
+                    }
+                    rest = Some(Pattern::parse_node(p, nodes)?);
+                }
+                _ => items.push(Pattern::parse_node(f, nodes)?),
+            }
+        }
+        Ok(Node::List { items, repeat, rest })
+    }
+
+    /// Parses a pattern for a binding form such as `let`, where `(? pred)`
+    /// patterns aren't allowed.
+    pub fn parse_binding(form: &Form) -> Result<Pattern, ErrType> {
+        let p = Pattern::parse(form)?;
+        if p.nodes.iter().any(|n| matches!(n, Node::Pred(..))) {
+            return Err(ErrType::syntax("(? pred) patterns can only be used in match"));
+        }
+        Ok(p)
+    }
+
+    fn root(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    /// Matches `v` against a pattern from `parse_binding`, raising a
+    /// `MatchError` if it doesn't fit.
+    pub fn destructure(&self, v: &Val) -> Result<Bindings, ErrType> {
+        match &self.nodes[..] {
+            [Node::Bind(s)] => return Ok(vec![(*s, v.clone())]),
+            [Node::Wildcard] => return Ok(Vec::new()),
+            _ => (),
+        }
+        match Matcher::new(self, v.clone()).run() {
+            Step::Matched(bindings) => Ok(bindings),
+            Step::Failed => Err(ErrType::no_match(v.clone())),
+            Step::Pred(..) => Err(ErrType::syntax("(? pred) patterns can only be used in match")),
+        }
+    }
+
+    /// Adds the variables node `i` binds to `out`, in order.
+    fn collect_binders(nodes: &[Node], i: usize, out: &mut Vec<Symbol>) {
+        match &nodes[i] {
+            Node::Bind(s) => out.push(*s),
+            Node::List { items, rest, .. } => {
+                items.iter().for_each(|p| Pattern::collect_binders(nodes, *p, out));
+                if let Some(r) = rest {
+                    Pattern::collect_binders(nodes, *r, out);
+                }
+            }
+            Node::Pred(_, Some(p)) => Pattern::collect_binders(nodes, *p, out),
+            _ => (),
+        }
+    }
+}
+
+/// The patterns of `match` clauses, each parsed the first time its clause is
+/// tried and kept while the clause's forms are alive.
+#[derive(Default)]
+pub struct PatternCache {
+    /// Keyed by the address of a clause's forms. The weak reference keeps
+    /// the address from being reused, and says whether the clause is alive.
+    patterns: HashMap<*const Form, (Weak<[Form]>, Pattern)>,
+    /// The number of patterns at which those of dead clauses are dropped.
+    prune_at: usize,
+}
+
+impl PatternCache {
+    /// The pattern of the match clause made of `clause`, the pattern's form
+    /// followed by the body.
+    pub fn get(&mut self, clause: &Rc<[Form]>) -> Result<Pattern, ErrType> {
+        let key = Rc::as_ptr(clause) as *const Form;
+        if let Some((_, p)) = self.patterns.get(&key) {
+            return Ok(p.clone());
+        }
+        let p = Pattern::parse(&clause[0])?;
+        if self.patterns.len() >= self.prune_at {
+            self.patterns.retain(|_, (c, _)| c.strong_count() > 0);
+            self.prune_at = (2 * self.patterns.len()).max(64);
+        }
+        self.patterns.insert(key, (Rc::downgrade(clause), p.clone()));
+        Ok(p)
+    }
+}
+
+/// How far a `Matcher` got.
+pub enum Step {
+    Matched(Bindings),
+    Failed,
+    /// Matching needs to know whether the procedure this form evaluates to
+    /// accepts this value; see `Matcher::answer`.
+    Pred(Form, Val),
+}
+
+/// Matches a value against a pattern a node at a time, so that it can stop at
+/// a `(? pred)` pattern for its predicate to be called, and carry on from
+/// there once it has the answer.
+#[derive(Clone)]
+pub struct Matcher {
+    pattern: Pattern,
+    /// What's left to do, the next task last.
+    tasks: Vec<Task>,
+    bindings: Bindings,
+    /// Where the bindings of each repeat being matched start.
+    marks: Vec<usize>,
+    /// The pattern, if any, that the value a predicate was asked about must
+    /// also match.
+    asked: Option<(Option<usize>, Val)>,
+    failed: bool,
+}
+
+#[derive(Clone)]
+enum Task {
+    Match(usize, Val),
+    /// Marks where the bindings of a repeat start.
+    Mark,
+    /// Gathers the bindings made since the last mark into a list per variable
+    /// of the repeat of the list at this index.
+    Collect(usize),
+}
+
+impl Matcher {
+    pub fn new(pattern: &Pattern, v: Val) -> Matcher {
+        Matcher {
+            pattern: pattern.clone(),
+            tasks: vec![Task::Match(pattern.root(), v)],
+            bindings: Vec::new(),
+            marks: Vec::new(),
+            asked: None,
+            failed: false,
+        }
+    }
+
+    /// Gives the answer of the predicate the last `Step::Pred` asked about.
+    pub fn answer(&mut self, accepted: bool) {
+        match self.asked.take() {
+            Some((p, v)) if accepted => self.tasks.extend(p.map(|p| Task::Match(p, v))),
+            _ => self.failed = true,
+        }
+    }
+
+    /// Matches until the pattern matches, fails to, or a predicate has to be
+    /// called.
+    pub fn run(&mut self) -> Step {
+        let nodes = Rc::clone(&self.pattern.nodes);
+        while let Some(task) = self.tasks.pop().filter(|_| !self.failed) {
+            let (i, v) = match task {
+                Task::Match(i, v) => (i, v),
+                Task::Mark => {
+                    self.marks.push(self.bindings.len());
+                    continue;
+                }
+                Task::Collect(i) => {
+                    self.collect(&nodes[i]);
+                    continue;
+                }
+            };
+            self.failed = !match &nodes[i] {
+                Node::Wildcard => true,
+                Node::Bind(s) => {
+                    self.bindings.push((*s, v));
+                    true
+                }
+                Node::Literal(lit) => *lit == v,
+                Node::Pred(pred, p) => {
+                    self.asked = Some((*p, v.clone()));
+                    return Step::Pred(pred.clone(), v);
+                }
+                Node::List { items, repeat, rest } => self.push_list(i, items, repeat, *rest, &v),
+            };
+        }
+        if self.failed {
+            Step::Failed
+        } else {
+            Step::Matched(mem::take(&mut self.bindings))
+        }
+    }
+
+    /// Queues matching list `v` element by element against the list pattern
+    /// at index `i`, if `v` has the right length.
+    fn push_list(&mut self, i: usize, items: &[usize], repeat: &Option<Repeat>, rest: Option<usize>, v: &Val) -> bool {
+        let l = match v {
+            Val::List(l) => l,
+            _ => return false,
+        };
+        let Some(r) = repeat else {
+            let fits = match rest {
+                Some(_) => l.len() >= items.len(),
+                None => l.len() == items.len(),
+            };
+            if !fits {
+                return false;
+            }
+            if let Some(rest) = rest {
+                let tail = (0..items.len()).fold(l.clone(), |tail, _| tail.rest());
+                self.tasks.push(Task::Match(rest, Val::List(tail)));
+            }
+            self.tasks.extend(items.iter().zip(&l[..]).rev().map(|(p, v)| Task::Match(*p, v.clone())));
+            return true;
+        };
+        let fixed = items.len() - 1;
+        if l.len() < fixed {
+            return false;
+        }
+        // The fixed items are matched first, then the repeated one.
+        let n = l.len() - fixed;
+        let k = r.index;
+        self.tasks.push(Task::Collect(i));
+        self.tasks.extend(l[k..k + n].iter().rev().map(|v| Task::Match(items[k], v.clone())));
+        self.tasks.push(Task::Mark);
+        let before = items[..k].iter().zip(&l[..k]);
+        let after = items[k + 1..].iter().zip(&l[k + n..]);
+        let fixed: Vec<_> = before.chain(after).map(|(p, v)| Task::Match(*p, v.clone())).collect();
+        self.tasks.extend(fixed.into_iter().rev());
+        true
+    }
+
+    /// Replaces the bindings made by the matches of a repeat with a list of
+    /// the matches of each of its variables.
+    fn collect(&mut self, list: &Node) {
+        let r = match list {
+            Node::List { repeat: Some(r), .. } => r,
+            _ => unreachable!("only lists with a repeat collect"),
+        };
+        let start = self.marks.pop().expect("a repeat's matches start with a mark");
+        let matches = self.bindings.split_off(start);
+        let mut seqs = vec![Vec::new(); r.vars.len()];
+        for chunk in matches.chunks(r.width.max(1)) {
+            for (seq, var) in seqs.iter_mut().zip(&r.vars) {
+                if let Some((_, v)) = chunk.iter().rev().find(|(s, _)| s == var) {
+                    seq.push(v.clone());
+                }
+            }
+        }
+        self.bindings.extend(r.vars.iter().zip(seqs).map(|(s, seq)| (*s, Val::List(seq.into()))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::read;
+
+    fn form(src: &str) -> Form {
+        read(src, "test").unwrap().remove(0)
+    }
+
+    fn pattern(src: &str) -> Pattern {
+        Pattern::parse(&form(src)).unwrap()
+    }
+
+    fn datum(src: &str) -> Val {
+        read(src, "test").unwrap()[0].to_val()
+    }
+
+    /// Matches `v` against `p`, answering every predicate with whether its
+    /// value is an int, and returns the bindings and the number of answers.
+    fn match_counting(p: &str, v: &str) -> (Option<Vec<(String, String)>>, usize) {
+        let mut m = Matcher::new(&pattern(p), datum(v));
+        let mut asked = 0;
+        loop {
+            match m.run() {
+                Step::Matched(bindings) => {
+                    let bindings = bindings.into_iter().map(|(s, v)| (s.to_string(), v.to_string())).collect();
+                    return (Some(bindings), asked);
+                }
+                Step::Failed => return (None, asked),
+                Step::Pred(_, v) => {
+                    asked += 1;
+                    m.answer(matches!(v, Val::Int(_)));
+                }
+            }
+        }
+    }
+
+    fn match_str(p: &str, v: &str) -> Option<Vec<(String, String)>> {
+        match_counting(p, v).0
+    }
+
+    fn binds(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(pairs.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect())
+    }
+
+    #[test]
+    fn test_literals_and_binders() {
+        assert_eq!(match_str("1", "1"), binds(&[]));
+        assert_eq!(match_str("1", "2"), None);
+        assert_eq!(match_str("'a", "a"), binds(&[]));
+        assert_eq!(match_str("_", "(1 2)"), binds(&[]));
+        assert_eq!(match_str("x", "(1 2)"), binds(&[("x", "(1, 2)")]));
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(match_str("(a (b _))", "(1 (2 3))"), binds(&[("a", "1"), ("b", "2")]));
+        assert_eq!(match_str("(a b)", "(1 2 3)"), None);
+        assert_eq!(match_str("(a . r)", "(1 2 3)"), binds(&[("a", "1"), ("r", "(2, 3)")]));
+        assert_eq!(match_str("(a . r)", "()"), None);
+        assert_eq!(match_str("()", "()"), binds(&[]));
+    }
+
+    #[test]
+    fn test_repeat() {
+        assert_eq!(match_str("(a ... z)", "(1 2 3)"), binds(&[("z", "3"), ("a", "(1, 2)")]));
+        assert_eq!(match_str("(a ...)", "()"), binds(&[("a", "()")]));
+        assert_eq!(match_str("((k v) ...)", "((a 1) (b 2))"), binds(&[("k", "('a, 'b)"), ("v", "(1, 2)")]));
+        assert_eq!(match_str("((k v) ...)", "((a 1) b)"), None);
+        assert_eq!(match_str("(x y ... z)", "(1)"), None);
+    }
+
+    #[test]
+    fn test_pred() {
+        assert_eq!(match_str("(? int?)", "1"), binds(&[]));
+        assert_eq!(match_str("(? int? n)", "1"), binds(&[("n", "1")]));
+        assert_eq!(match_str("((? int?) ...)", "(1 2 a)"), None);
+        assert_eq!(match_counting("((? int?) ...)", "(1 2 3 4)"), (binds(&[]), 4));
+        assert_eq!(match_counting("((? int? x) (? int? y))", "(a 2)"), (None, 1));
+        assert_eq!(match_str("((? (lambda (x) #t) x) ...)", "(1 2)"), binds(&[("x", "(1, 2)")]));
+    }
+
+    #[test]
+    fn test_destructure() -> Result<(), ErrType> {
+        let p = Pattern::parse_binding(&form("(a (b . c))"))?;
+        let bindings = p.destructure(&datum("(1 (2 3))"))?;
+        assert_eq!(bindings, vec![
+            (Symbol::intern("a"), Val::Int(1)),
+            (Symbol::intern("b"), Val::Int(2)),
+            (Symbol::intern("c"), datum("(3)")),
+        ]);
+        assert_eq!(p.destructure(&datum("(1 2)")), Err(ErrType::no_match(datum("(1 2)"))));
+        assert!(Pattern::parse_binding(&form("(a (? int? b))")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |src: &str| Pattern::parse(&form(src));
+        assert!(parse("(... a)").is_err());
+        assert!(parse("(a ... b ...)").is_err());
+        assert!(parse("(a . b c)").is_err());
+        assert!(parse("(?)").is_err());
+    }
+}
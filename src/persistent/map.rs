@@ -0,0 +1,359 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::mem;
+use std::rc::Rc;
+
+use super::{BITS, MASK};
+
+enum Entry<K, V> {
+    Leaf(u64, K, V),
+    Collision(u64, Vec<(K, V)>),
+    Node(Rc<Node<K, V>>),
+}
+
+/// A trie node holding an entry for each bit set in `bitmap`, in bit order.
+struct Node<K, V> {
+    bitmap: u32,
+    entries: Vec<Entry<K, V>>,
+}
+
+/// A persistent hash map: a hash array mapped trie whose updates copy only
+/// the nodes on the path to the changed entry.
+pub struct PMap<K, V> {
+    root: Rc<Node<K, V>>,
+    len: usize,
+}
+
+fn hash_of<K: Hash>(k: &K) -> u64 {
+    let mut s = DefaultHasher::new();
+    k.hash(&mut s);
+    s.finish()
+}
+
+fn bit(h: u64, shift: usize) -> u32 {
+    1 << ((h >> shift) as usize & MASK)
+}
+
+impl<K: Clone, V: Clone> Clone for Entry<K, V> {
+    fn clone(&self) -> Self {
+        match self {
+            Entry::Leaf(h, k, v) => Entry::Leaf(*h, k.clone(), v.clone()),
+            Entry::Collision(h, pairs) => Entry::Collision(*h, pairs.clone()),
+            Entry::Node(n) => Entry::Node(Rc::clone(n)),
+        }
+    }
+}
+
+impl<K, V> Entry<K, V> {
+    fn hash(&self) -> u64 {
+        match self {
+            Entry::Leaf(h, ..) | Entry::Collision(h, _) => *h,
+            Entry::Node(_) => unreachable!("trie nodes have no single hash"),
+        }
+    }
+}
+
+impl<K: Clone + Eq, V: Clone> Node<K, V> {
+    fn index(&self, bit: u32) -> usize {
+        (self.bitmap & (bit - 1)).count_ones() as usize
+    }
+    /// A node holding two entries whose hashes differ, nesting as deep as
+    /// needed for them to land in different slots.
+    fn pair(shift: usize, a: Entry<K, V>, b: Entry<K, V>) -> Node<K, V> {
+        let (bit_a, bit_b) = (bit(a.hash(), shift), bit(b.hash(), shift));
+        if bit_a == bit_b {
+            let child = Node::pair(shift + BITS, a, b);
+            Node { bitmap: bit_a, entries: vec![Entry::Node(Rc::new(child))] }
+        } else if bit_a < bit_b {
+            Node { bitmap: bit_a | bit_b, entries: vec![a, b] }
+        } else {
+            Node { bitmap: bit_a | bit_b, entries: vec![b, a] }
+        }
+    }
+    fn insert(&self, shift: usize, h: u64, k: K, v: V) -> (Node<K, V>, bool) {
+        let b = bit(h, shift);
+        let idx = self.index(b);
+        let mut entries = self.entries.clone();
+        if self.bitmap & b == 0 {
+            entries.insert(idx, Entry::Leaf(h, k, v));
+            return (Node { bitmap: self.bitmap | b, entries }, true);
+        }
+        let added = match &self.entries[idx] {
+            Entry::Leaf(_, k2, _) if *k2 == k => {
+                entries[idx] = Entry::Leaf(h, k, v);
+                false
+            }
+            Entry::Leaf(h2, k2, v2) if *h2 == h => {
+                entries[idx] = Entry::Collision(h, vec![(k2.clone(), v2.clone()), (k, v)]);
+                true
+            }
+            Entry::Collision(h2, pairs) if *h2 == h => {
+                let mut pairs = pairs.clone();
+                let added = match pairs.iter_mut().find(|(k2, _)| *k2 == k) {
+                    Some(pair) => {
+                        pair.1 = v;
+                        false
+                    }
+                    None => {
+                        pairs.push((k, v));
+                        true
+                    }
+                };
+                entries[idx] = Entry::Collision(h, pairs);
+                added
+            }
+            Entry::Node(child) => {
+                let (child, added) = child.insert(shift + BITS, h, k, v);
+                entries[idx] = Entry::Node(Rc::new(child));
+                added
+            }
+            existing => {
+                let child = Node::pair(shift + BITS, existing.clone(), Entry::Leaf(h, k, v));
+                entries[idx] = Entry::Node(Rc::new(child));
+                true
+            }
+        };
+        (Node { bitmap: self.bitmap, entries }, added)
+    }
+    /// Returns the node without `k`, or `None` if `k` isn't present.
+    fn remove(&self, shift: usize, h: u64, k: &K) -> Option<Node<K, V>> {
+        let b = bit(h, shift);
+        if self.bitmap & b == 0 {
+            return None;
+        }
+        let idx = self.index(b);
+        let replacement = match &self.entries[idx] {
+            Entry::Leaf(_, k2, _) if k2 == k => None,
+            Entry::Leaf(..) => return None,
+            Entry::Collision(h2, pairs) => {
+                let pos = pairs.iter().position(|(k2, _)| k2 == k)?;
+                let mut pairs = pairs.clone();
+                pairs.remove(pos);
+                match pairs.len() {
+                    1 => {
+                        let (k, v) = pairs.remove(0);
+                        Some(Entry::Leaf(*h2, k, v))
+                    }
+                    _ => Some(Entry::Collision(*h2, pairs)),
+                }
+            }
+            Entry::Node(child) => {
+                let child = child.remove(shift + BITS, h, k)?;
+                match &child.entries[..] {
+                    [] => None,
+                    [Entry::Leaf(..)] | [Entry::Collision(..)] => Some(child.entries[0].clone()),
+                    _ => Some(Entry::Node(Rc::new(child))),
+                }
+            }
+        };
+        let mut entries = self.entries.clone();
+        match replacement {
+            Some(e) => {
+                entries[idx] = e;
+                Some(Node { bitmap: self.bitmap, entries })
+            }
+            None => {
+                entries.remove(idx);
+                Some(Node { bitmap: self.bitmap & !b, entries })
+            }
+        }
+    }
+    fn collect<'a>(&'a self, out: &mut Vec<(&'a K, &'a V)>) {
+        for e in self.entries.iter() {
+            match e {
+                Entry::Leaf(_, k, v) => out.push((k, v)),
+                Entry::Collision(_, pairs) => out.extend(pairs.iter().map(|(k, v)| (k, v))),
+                Entry::Node(n) => n.collect(out),
+            }
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> PMap<K, V> {
+    pub fn new() -> Self {
+        PMap { root: Rc::new(Node { bitmap: 0, entries: Vec::new() }), len: 0 }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn get(&self, k: &K) -> Option<&V> {
+        let h = hash_of(k);
+        let mut node = &*self.root;
+        let mut shift = 0;
+        loop {
+            let b = bit(h, shift);
+            if node.bitmap & b == 0 {
+                return None;
+            }
+            match &node.entries[node.index(b)] {
+                Entry::Leaf(_, k2, v) => return if k2 == k { Some(v) } else { None },
+                Entry::Collision(_, pairs) => {
+                    return pairs.iter().find(|(k2, _)| k2 == k).map(|(_, v)| v);
+                }
+                Entry::Node(n) => {
+                    node = n;
+                    shift += BITS;
+                }
+            }
+        }
+    }
+    /// Returns a copy of this map with `k` bound to `v`.
+    pub fn insert(&self, k: K, v: V) -> Self {
+        let (root, added) = self.root.insert(0, hash_of(&k), k, v);
+        PMap { root: Rc::new(root), len: self.len + added as usize }
+    }
+    /// Returns a copy of this map without `k`.
+    pub fn remove(&self, k: &K) -> Self {
+        match self.root.remove(0, hash_of(k), k) {
+            Some(root) => PMap { root: Rc::new(root), len: self.len - 1 },
+            None => self.clone(),
+        }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut out = Vec::with_capacity(self.len);
+        self.root.collect(&mut out);
+        out.into_iter()
+    }
+    /// Empties this map, moving the entries of the nodes no other map shares
+    /// to `out`, so that dropping entries that are themselves maps needn't
+    /// recurse.
+    pub fn take_unshared(&mut self, out: &mut Vec<(K, V)>) {
+        let empty = Rc::new(Node { bitmap: 0, entries: Vec::new() });
+        let mut nodes = vec![mem::replace(&mut self.root, empty)];
+        self.len = 0;
+        while let Some(node) = nodes.pop() {
+            let node = match Rc::try_unwrap(node) {
+                Ok(node) => node,
+                Err(_) => continue,
+            };
+            for e in node.entries {
+                match e {
+                    Entry::Leaf(_, k, v) => out.push((k, v)),
+                    Entry::Collision(_, pairs) => out.extend(pairs),
+                    Entry::Node(n) => nodes.push(n),
+                }
+            }
+        }
+    }
+    /// The entries of the nodes no other map shares, which belong to this
+    /// map alone.
+    pub fn unshared(&self) -> Vec<(&K, &V)> {
+        let mut out = Vec::new();
+        let mut nodes = vec![&self.root];
+        while let Some(node) = nodes.pop() {
+            if Rc::strong_count(node) > 1 {
+                continue;
+            }
+            for e in &node.entries {
+                match e {
+                    Entry::Leaf(_, k, v) => out.push((k, v)),
+                    Entry::Collision(_, pairs) => out.extend(pairs.iter().map(|(k, v)| (k, v))),
+                    Entry::Node(n) => nodes.push(n),
+                }
+            }
+        }
+        out
+    }
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Default for PMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Clone for PMap<K, V> {
+    fn clone(&self) -> Self {
+        PMap { root: Rc::clone(&self.root), len: self.len }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> FromIterator<(K, V)> for PMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        iter.into_iter().fold(PMap::new(), |m, (k, v)| m.insert(k, v))
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + PartialEq> PartialEq for PMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + Eq> Eq for PMap<K, V> {}
+
+impl<K: Clone + Eq + Hash + fmt::Debug, V: Clone + fmt::Debug> fmt::Debug for PMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A key whose hash is just its value modulo 4, to force collisions.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Colliding(u32);
+
+    impl Hash for Colliding {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            (self.0 % 4).hash(state)
+        }
+    }
+
+    #[test]
+    fn test_insert_get() {
+        let n = 20_000;
+        let m: PMap<u32, u32> = (0..n).map(|i| (i, i * 2)).collect();
+        assert_eq!(m.len(), n as usize);
+        assert!((0..n).all(|i| m.get(&i) == Some(&(i * 2))));
+        assert_eq!(m.get(&n), None);
+        let m2 = m.insert(0, 7);
+        assert_eq!(m2.len(), m.len());
+        assert_eq!(m2.get(&0), Some(&7));
+        assert_eq!(m.get(&0), Some(&0));
+    }
+
+    #[test]
+    fn test_remove() {
+        let n = 5_000;
+        let m: PMap<u32, u32> = (0..n).map(|i| (i, i)).collect();
+        let evens = (0..n).filter(|i| i % 2 == 1).fold(m.clone(), |m, i| m.remove(&i));
+        assert_eq!(evens.len(), (n / 2) as usize);
+        assert!((0..n).all(|i| (evens.get(&i) == Some(&i)) == (i % 2 == 0)));
+        let empty = (0..n).fold(m.clone(), |m, i| m.remove(&i));
+        assert!(empty.is_empty());
+        assert_eq!(empty.iter().count(), 0);
+        assert_eq!(m.remove(&n).len(), m.len());
+    }
+
+    #[test]
+    fn test_collisions() {
+        let m: PMap<Colliding, u32> = (0..20).map(|i| (Colliding(i), i)).collect();
+        assert_eq!(m.len(), 20);
+        assert!((0..20).all(|i| m.get(&Colliding(i)) == Some(&i)));
+        let m = (0..20).filter(|i| i % 3 != 0).fold(m, |m, i| m.remove(&Colliding(i)));
+        assert_eq!(m.len(), 7);
+        assert!((0..20).all(|i| (m.get(&Colliding(i)) == Some(&i)) == (i % 3 == 0)));
+    }
+
+    #[test]
+    fn test_eq_ignores_insertion_order() {
+        let a: PMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
+        let b: PMap<u32, u32> = (0..100).rev().map(|i| (i, i)).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, b.insert(0, 1));
+    }
+}
@@ -0,0 +1,11 @@
+pub mod map;
+pub mod set;
+pub mod vector;
+
+pub use map::PMap;
+pub use set::PSet;
+pub use vector::PVector;
+
+const BITS: usize = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
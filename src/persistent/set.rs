@@ -0,0 +1,128 @@
+use std::fmt;
+use std::hash::Hash;
+use std::iter::FromIterator;
+
+use super::map::PMap;
+
+/// A persistent hash set, stored as a `PMap` with unit values.
+pub struct PSet<T> {
+    map: PMap<T, ()>,
+}
+
+impl<T: Clone + Eq + Hash> PSet<T> {
+    pub fn new() -> Self {
+        PSet { map: PMap::new() }
+    }
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+    pub fn contains(&self, x: &T) -> bool {
+        self.map.get(x).is_some()
+    }
+    /// Returns a copy of this set with `x` added.
+    pub fn insert(&self, x: T) -> Self {
+        PSet { map: self.map.insert(x, ()) }
+    }
+    /// Returns a copy of this set without `x`.
+    pub fn remove(&self, x: &T) -> Self {
+        PSet { map: self.map.remove(x) }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.map.keys()
+    }
+    /// Empties this set, moving the members no other set shares to `out`;
+    /// see `PMap::take_unshared`.
+    pub fn take_unshared(&mut self, out: &mut Vec<T>) {
+        let mut entries = Vec::new();
+        self.map.take_unshared(&mut entries);
+        out.extend(entries.into_iter().map(|(x, ())| x));
+    }
+    /// The members no other set shares; see `PMap::unshared`.
+    pub fn unshared(&self) -> Vec<&T> {
+        self.map.unshared().into_iter().map(|(x, ())| x).collect()
+    }
+    pub fn union(&self, other: &Self) -> Self {
+        let (big, small) = if self.len() >= other.len() { (self, other) } else { (other, self) };
+        small.iter().fold(big.clone(), |s, x| s.insert(x.clone()))
+    }
+    pub fn intersection(&self, other: &Self) -> Self {
+        let (big, small) = if self.len() >= other.len() { (self, other) } else { (other, self) };
+        small.iter().filter(|x| big.contains(x)).cloned().collect()
+    }
+    pub fn difference(&self, other: &Self) -> Self {
+        if self.len() <= other.len() {
+            self.iter().filter(|x| !other.contains(x)).cloned().collect()
+        } else {
+            other.iter().fold(self.clone(), |s, x| s.remove(x))
+        }
+    }
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.len() <= other.len() && self.iter().all(|x| other.contains(x))
+    }
+}
+
+impl<T: Clone + Eq + Hash> Default for PSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for PSet<T> {
+    fn clone(&self) -> Self {
+        PSet { map: self.map.clone() }
+    }
+}
+
+impl<T: Clone + Eq + Hash> FromIterator<T> for PSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        PSet { map: iter.into_iter().map(|x| (x, ())).collect() }
+    }
+}
+
+impl<T: Clone + Eq + Hash> PartialEq for PSet<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl<T: Clone + Eq + Hash> Eq for PSet<T> {}
+
+impl<T: Clone + Eq + Hash + fmt::Debug> fmt::Debug for PSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(a: u32, b: u32) -> PSet<u32> {
+        (a..b).collect()
+    }
+
+    #[test]
+    fn test_set_ops() {
+        let a = range(0, 100);
+        let b = range(50, 150);
+        assert_eq!(a.union(&b), range(0, 150));
+        assert_eq!(a.intersection(&b), range(50, 100));
+        assert_eq!(a.difference(&b), range(0, 50));
+        assert_eq!(b.difference(&range(0, 60)), range(60, 150));
+        assert!(range(10, 20).is_subset(&a));
+        assert!(!b.is_subset(&a));
+        assert!(PSet::new().is_subset(&a));
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let s = range(0, 10);
+        assert_eq!(s.insert(3).len(), 10);
+        assert!(s.insert(10).contains(&10));
+        assert!(!s.remove(&3).contains(&3));
+        assert!(s.contains(&3));
+    }
+}
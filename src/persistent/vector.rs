@@ -0,0 +1,213 @@
+use std::fmt;
+use std::iter::FromIterator;
+use std::mem;
+use std::rc::Rc;
+
+use super::{BITS, MASK, WIDTH};
+
+enum Node<T> {
+    Branch(Vec<Rc<Node<T>>>),
+    Leaf(Vec<T>),
+}
+
+/// A persistent vector: a 32-way trie whose updates copy only the path from
+/// the root to the changed leaf, sharing everything else with the original.
+pub struct PVector<T> {
+    root: Rc<Node<T>>,
+    shift: usize,
+    len: usize,
+}
+
+impl<T: Clone> PVector<T> {
+    pub fn new() -> Self {
+        PVector { root: Rc::new(Node::Leaf(Vec::new())), shift: 0, len: 0 }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn get(&self, i: usize) -> Option<&T> {
+        if i >= self.len {
+            return None;
+        }
+        let mut node = &*self.root;
+        let mut level = self.shift;
+        loop {
+            match node {
+                Node::Branch(children) => {
+                    node = &children[(i >> level) & MASK];
+                    level -= BITS;
+                }
+                Node::Leaf(items) => return items.get(i & MASK),
+            }
+        }
+    }
+    /// Returns a copy of this vector with `v` appended.
+    pub fn push(&self, v: T) -> Self {
+        if self.len == WIDTH << self.shift {
+            let path = PVector::new_path(self.shift, v);
+            let root = Node::Branch(vec![Rc::clone(&self.root), Rc::new(path)]);
+            PVector { root: Rc::new(root), shift: self.shift + BITS, len: self.len + 1 }
+        } else {
+            let root = PVector::push_in(&self.root, self.shift, self.len, v);
+            PVector { root: Rc::new(root), shift: self.shift, len: self.len + 1 }
+        }
+    }
+    /// Returns a copy of this vector with the element at `i` replaced by `v`,
+    /// or `None` if `i` is out of range.
+    pub fn set(&self, i: usize, v: T) -> Option<Self> {
+        if i >= self.len {
+            return None;
+        }
+        let root = PVector::set_in(&self.root, self.shift, i, v);
+        Some(PVector { root: Rc::new(root), shift: self.shift, len: self.len })
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len).filter_map(move |i| self.get(i))
+    }
+    /// Empties this vector, moving the elements of the nodes no other vector
+    /// shares to `out`, so that dropping elements that are themselves
+    /// vectors needn't recurse.
+    pub fn take_unshared(&mut self, out: &mut Vec<T>) {
+        let mut nodes = vec![mem::replace(&mut self.root, Rc::new(Node::Leaf(Vec::new())))];
+        self.shift = 0;
+        self.len = 0;
+        while let Some(node) = nodes.pop() {
+            match Rc::try_unwrap(node) {
+                Ok(Node::Branch(children)) => nodes.extend(children),
+                Ok(Node::Leaf(items)) => out.extend(items),
+                Err(_) => (),
+            }
+        }
+    }
+
+    /// The elements of the nodes no other vector shares, which belong to
+    /// this vector alone.
+    pub fn unshared(&self) -> Vec<&T> {
+        let mut out = Vec::new();
+        let mut nodes = vec![&self.root];
+        while let Some(node) = nodes.pop() {
+            if Rc::strong_count(node) > 1 {
+                continue;
+            }
+            match &**node {
+                Node::Branch(children) => nodes.extend(children),
+                Node::Leaf(items) => out.extend(items),
+            }
+        }
+        out
+    }
+
+    fn new_path(level: usize, v: T) -> Node<T> {
+        if level == 0 {
+            Node::Leaf(vec![v])
+        } else {
+            Node::Branch(vec![Rc::new(PVector::new_path(level - BITS, v))])
+        }
+    }
+    fn push_in(node: &Node<T>, level: usize, i: usize, v: T) -> Node<T> {
+        match node {
+            Node::Leaf(items) => {
+                let mut items = items.clone();
+                items.push(v);
+                Node::Leaf(items)
+            }
+            Node::Branch(children) => {
+                let mut children = children.clone();
+                let idx = (i >> level) & MASK;
+                if idx < children.len() {
+                    children[idx] = Rc::new(PVector::push_in(&children[idx], level - BITS, i, v));
+                } else {
+                    children.push(Rc::new(PVector::new_path(level - BITS, v)));
+                }
+                Node::Branch(children)
+            }
+        }
+    }
+    fn set_in(node: &Node<T>, level: usize, i: usize, v: T) -> Node<T> {
+        match node {
+            Node::Leaf(items) => {
+                let mut items = items.clone();
+                items[i & MASK] = v;
+                Node::Leaf(items)
+            }
+            Node::Branch(children) => {
+                let mut children = children.clone();
+                let idx = (i >> level) & MASK;
+                children[idx] = Rc::new(PVector::set_in(&children[idx], level - BITS, i, v));
+                Node::Branch(children)
+            }
+        }
+    }
+}
+
+impl<T: Clone> Default for PVector<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for PVector<T> {
+    fn clone(&self) -> Self {
+        PVector { root: Rc::clone(&self.root), shift: self.shift, len: self.len }
+    }
+}
+
+impl<T: Clone> FromIterator<T> for PVector<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter().fold(PVector::new(), |v, x| v.push(x))
+    }
+}
+
+impl<T: Clone + PartialEq> PartialEq for PVector<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Clone + Eq> Eq for PVector<T> {}
+
+impl<T: Clone + fmt::Debug> fmt::Debug for PVector<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_get() {
+        let n = 40_000;
+        let v: PVector<usize> = (0..n).collect();
+        assert_eq!(v.len(), n);
+        assert!((0..n).all(|i| v.get(i) == Some(&i)));
+        assert_eq!(v.get(n), None);
+    }
+
+    #[test]
+    fn test_set_shares_structure() {
+        let v: PVector<usize> = (0..2000).collect();
+        let v2 = v.set(1500, 0).unwrap();
+        assert_eq!(v.get(1500), Some(&1500));
+        assert_eq!(v2.get(1500), Some(&0));
+        if let (Node::Branch(a), Node::Branch(b)) = (&*v.root, &*v2.root) {
+            assert!(Rc::ptr_eq(&a[0], &b[0]));
+            assert!(!Rc::ptr_eq(&a[1], &b[1]));
+        } else {
+            panic!("test_set_shares_structure");
+        }
+        assert!(v.set(2000, 0).is_none());
+    }
+
+    #[test]
+    fn test_eq() {
+        let a: PVector<i32> = (0..100).collect();
+        let b = a.set(3, 0).unwrap().set(3, 3).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, a.push(1));
+    }
+}
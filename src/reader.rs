@@ -0,0 +1,348 @@
+use std::iter::Peekable;
+use std::ops::Deref;
+use std::rc::Rc;
+use std::str::Chars;
+
+use super::diagnostic::Span;
+use super::errors::ErrType;
+use super::symbol::Symbol;
+use super::types::Val;
+
+/// A parsed form, keeping the span of every subform so that errors can
+/// point back into the source.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Form {
+    pub kind: FormKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FormKind {
+    Atom(Val),
+    List(Rc<[Form]>),
+    Vector(Rc<[Form]>),
+    Map(Rc<[Form]>),
+    Set(Rc<[Form]>),
+}
+
+/// A run of forms sharing storage with the list it was taken from, such as
+/// the body of a procedure, so the evaluator can keep it without copying.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Forms {
+    items: Rc<[Form]>,
+    start: usize,
+}
+
+impl Forms {
+    /// The forms of `items` from `start` on.
+    pub fn new(items: &Rc<[Form]>, start: usize) -> Self {
+        Forms { items: Rc::clone(items), start: start.min(items.len()) }
+    }
+    pub fn rest(&self) -> Forms {
+        Forms::new(&self.items, self.start + 1)
+    }
+}
+
+impl From<Vec<Form>> for Forms {
+    fn from(v: Vec<Form>) -> Self {
+        Forms { items: v.into(), start: 0 }
+    }
+}
+
+impl Deref for Forms {
+    type Target = [Form];
+
+    fn deref(&self) -> &[Form] {
+        &self.items[self.start..]
+    }
+}
+
+impl Form {
+    pub fn to_val(&self) -> Val {
+        match &self.kind {
+            FormKind::Atom(v) => v.clone(),
+            FormKind::List(items) => {
+                Val::List(items.iter().map(Form::to_val).collect::<Vec<_>>().into())
+            }
+            FormKind::Vector(items) => Val::vector(items.iter().map(Form::to_val).collect()),
+            FormKind::Map(items) => {
+                Val::Map(items.chunks(2).map(|kv| (kv[0].to_val(), kv[1].to_val())).collect())
+            }
+            FormKind::Set(items) => Val::Set(items.iter().map(Form::to_val).collect()),
+        }
+    }
+}
+
+/// How deeply forms may nest. The reader and much of what handles forms and
+/// values recurse on nested ones, so deeper source is refused rather than
+/// left to overflow the stack.
+pub const MAX_DEPTH: usize = 256;
+
+struct Reader<'s> {
+    chars: Peekable<Chars<'s>>,
+    file: Rc<str>,
+    line: usize,
+    col: usize,
+    /// The number of forms being read that the next form would be inside.
+    depth: usize,
+}
+
+impl<'s> Reader<'s> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+    fn span_from(&self, (line, col): (usize, usize)) -> Span {
+        Span { file: Rc::clone(&self.file), line, col, end_line: self.line, end_col: self.col }
+    }
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == ';' {
+                while !matches!(self.next(), Some('\n') | None) {}
+            } else if c.is_whitespace() {
+                self.next();
+            } else {
+                break;
+            }
+        }
+    }
+    fn is_delimiter(c: char) -> bool {
+        c.is_whitespace() || matches!(c, '(' | ')' | '{' | '}' | '"' | ';' | '\'')
+    }
+    fn read_form(&mut self) -> Result<Option<Form>, ErrType> {
+        self.skip_whitespace();
+        if self.depth == MAX_DEPTH {
+            let start = (self.line, self.col);
+            return Err(ErrType::syntax("forms nested too deeply").at(self.span_from(start)));
+        }
+        self.depth += 1;
+        let form = self.read_nested();
+        self.depth -= 1;
+        form
+    }
+    /// Reads a form at the current depth, which `read_form` has checked.
+    fn read_nested(&mut self) -> Result<Option<Form>, ErrType> {
+        let start = (self.line, self.col);
+        let kind = match self.peek() {
+            None => return Ok(None),
+            Some('(') => {
+                self.next();
+                FormKind::List(self.read_list(start, ')')?.into())
+            }
+            Some('{') => {
+                self.next();
+                let items = self.read_list(start, '}')?;
+                if !items.len().is_multiple_of(2) {
+                    return Err(ErrType::syntax("map literal needs an even number of forms")
+                        .at(self.span_from(start)));
+                }
+                FormKind::Map(items.into())
+            }
+            Some(c @ ')') | Some(c @ '}') => {
+                self.next();
+                let msg = format!("unexpected {}", c);
+                return Err(ErrType::syntax(&msg).at(self.span_from(start)));
+            }
+            Some('\'') => {
+                self.next();
+                let quote = Form {
+                    kind: FormKind::Atom(Val::Symbol(Symbol::intern("quote"))),
+                    span: self.span_from(start),
+                };
+                let quoted = self.read_form()?
+                    .ok_or_else(|| ErrType::syntax("expected a form after '").at(self.span_from(start)))?;
+                FormKind::List(vec![quote, quoted].into())
+            }
+            Some('"') => {
+                self.next();
+                FormKind::Atom(self.read_string(start)?)
+            }
+            Some(_) => {
+                let token = self.read_token();
+                if token == "#" && self.peek() == Some('(') {
+                    self.next();
+                    FormKind::Vector(self.read_list(start, ')')?.into())
+                } else if token == "#" && self.peek() == Some('{') {
+                    self.next();
+                    FormKind::Set(self.read_list(start, '}')?.into())
+                } else {
+                    FormKind::Atom(self.read_atom(&token, start)?)
+                }
+            }
+        };
+        Ok(Some(Form { kind, span: self.span_from(start) }))
+    }
+    fn read_list(&mut self, start: (usize, usize), close: char) -> Result<Vec<Form>, ErrType> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => {
+                    let open = if close == '}' { '{' } else { '(' };
+                    let msg = format!("unclosed {}", open);
+                    return Err(ErrType::syntax(&msg).at(self.span_from(start)));
+                }
+                Some(c) if c == close => {
+                    self.next();
+                    return Ok(items);
+                }
+                Some(_) => items.extend(self.read_form()?),
+            }
+        }
+    }
+    fn read_string(&mut self, start: (usize, usize)) -> Result<Val, ErrType> {
+        let mut chars = Vec::new();
+        loop {
+            let c = match self.next() {
+                None => return Err(ErrType::syntax("unclosed \"").at(self.span_from(start))),
+                Some('"') => return Ok(Val::List(chars.into())),
+                Some('\\') => match self.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some(c @ '\\') | Some(c @ '"') => c,
+                    _ => return Err(ErrType::syntax("unknown escape").at(self.span_from(start))),
+                },
+                Some(c) => c,
+            };
+            chars.push(Val::Char(c));
+        }
+    }
+    fn read_token(&mut self) -> String {
+        let mut token = String::new();
+        while let Some(c) = self.peek() {
+            if Reader::is_delimiter(c) && (token != "#\\" || c.is_whitespace()) {
+                break;
+            }
+            token.push(c);
+            self.next();
+        }
+        token
+    }
+    fn read_atom(&mut self, token: &str, start: (usize, usize)) -> Result<Val, ErrType> {
+        if token == "#\\" {
+            if let Some(c) = self.next() {
+                return Ok(Val::Char(c));
+            }
+        }
+        if let Ok(i) = token.parse() {
+            return Ok(Val::Int(i));
+        }
+        match token {
+            "#t" => Ok(Val::Bool(true)),
+            "#f" => Ok(Val::Bool(false)),
+            "#\\space" => Ok(Val::Char(' ')),
+            "#\\newline" => Ok(Val::Char('\n')),
+            "#\\tab" => Ok(Val::Char('\t')),
+            t if t.starts_with("#\\") && t.chars().count() == 3 => {
+                Ok(Val::Char(t.chars().nth(2).unwrap_or_default()))
+            }
+            t if t.starts_with('#') && !t.starts_with("#!") => {
+                Err(ErrType::syntax(&format!("unknown syntax {}", t)).at(self.span_from(start)))
+            }
+            t => Ok(Val::Symbol(Symbol::intern(t))),
+        }
+    }
+}
+
+/// Reads every form in `source`, attributing spans to `file`.
+pub fn read(source: &str, file: &str) -> Result<Vec<Form>, ErrType> {
+    let mut r = Reader { chars: source.chars().peekable(), file: Rc::from(file), line: 1, col: 1, depth: 0 };
+    let mut forms = Vec::new();
+    while let Some(form) = r.read_form()? {
+        forms.push(form);
+    }
+    Ok(forms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_vals(src: &str) -> Result<Vec<Val>, ErrType> {
+        Ok(read(src, "test")?.iter().map(Form::to_val).collect())
+    }
+
+    fn sym(name: &str) -> Val {
+        Val::Symbol(Symbol::intern(name))
+    }
+
+    #[test]
+    fn test_read_atoms() -> Result<(), ErrType> {
+        let vals = read_vals("1 -2 #t #f #\\a #\\space #\\( foo - #!optional")?;
+        let expected = vec![
+            Val::Int(1), Val::Int(-2), Val::Bool(true), Val::Bool(false), Val::Char('a'),
+            Val::Char(' '), Val::Char('('), sym("foo"), sym("-"), sym("#!optional"),
+        ];
+        assert_eq!(vals, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_list() -> Result<(), ErrType> {
+        let vals = read_vals("(+ 1 (f 'x)) ; comment\n\"hi\"")?;
+        let quoted = Val::List(vec![sym("quote"), sym("x")].into());
+        let inner = Val::List(vec![sym("f"), quoted].into());
+        let string = Val::List(vec![Val::Char('h'), Val::Char('i')].into());
+        assert_eq!(vals, vec![Val::List(vec![sym("+"), Val::Int(1), inner].into()), string]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_collections() -> Result<(), ErrType> {
+        let vals = read_vals("#(1 #\\a) {1 2} #() #{1 2 1}")?;
+        let m = vec![(Val::Int(1), Val::Int(2))].into_iter().collect();
+        let s = vec![Val::Int(1), Val::Int(2)].into_iter().collect();
+        assert_eq!(*vals[0].unwrap_vector()?.borrow(), vec![Val::Int(1), Val::Char('a')]);
+        assert_eq!(vals[1], Val::Map(m));
+        assert!(vals[2].unwrap_vector()?.borrow().is_empty());
+        assert_eq!(vals[3], Val::Set(s));
+        assert!(read("{1}", "test").is_err());
+        assert!(read("{1 2", "test").is_err());
+        assert!(read("(1}", "test").is_err());
+        assert!(read("#{1", "test").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_spans() -> Result<(), ErrType> {
+        let forms = read("(a\n  (b c))", "test")?;
+        let outer = &forms[0].span;
+        assert_eq!((outer.line, outer.col, outer.end_line, outer.end_col), (1, 1, 2, 9));
+        if let FormKind::List(items) = &forms[0].kind {
+            let inner = &items[1].span;
+            assert_eq!((inner.line, inner.col, inner.end_line, inner.end_col), (2, 3, 2, 8));
+        } else {
+            panic!("test_read_spans");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let err = read("(a\n (b)", "test").unwrap_err();
+        assert_eq!(err.to_string(), "test:1:1: Syntax error: unclosed (");
+        let err = read("a )", "test").unwrap_err();
+        assert_eq!(err.to_string(), "test:1:3: Syntax error: unexpected )");
+        assert!(read("#q", "test").is_err());
+    }
+
+    #[test]
+    fn test_read_depth() -> Result<(), ErrType> {
+        let nested = |n| format!("{}{}", "(".repeat(n), ")".repeat(n));
+        assert_eq!(read(&nested(MAX_DEPTH), "test")?.len(), 1);
+        let err = read(&nested(MAX_DEPTH + 1), "test").unwrap_err();
+        assert_eq!(err.to_string(), format!("test:1:{}: Syntax error: forms nested too deeply", MAX_DEPTH + 1));
+        assert!(read(&"(".repeat(200_000), "test").is_err());
+        assert!(read(&format!("{}a", "'".repeat(200_000)), "test").is_err());
+        Ok(())
+    }
+}
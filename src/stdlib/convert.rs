@@ -0,0 +1,93 @@
+use std::convert::TryFrom;
+
+use crate::env::{Arity, Env};
+use crate::errors::ErrType;
+use crate::types::{Args, Res, Val};
+
+pub fn char_to_integer(args: Args) -> Res {
+    Ok(Val::Int(*args[0].unwrap_char()? as i64))
+}
+
+pub fn integer_to_char(args: Args) -> Res {
+    let i = *args[0].unwrap_int()?;
+    u32::try_from(i).ok().and_then(char::from_u32)
+        .map(Val::Char)
+        .ok_or_else(|| ErrType::user("not a character code", args))
+}
+
+pub fn number_to_string(args: Args) -> Res {
+    Ok(Val::string(&args[0].unwrap_int()?.to_string()))
+}
+
+/// `(string->number s)` parses `s` as an integer, returning `#f` if it isn't one.
+pub fn string_to_number(args: Args) -> Res {
+    let s = args[0].unwrap_string()?;
+    Ok(s.parse().map_or(Val::Bool(false), Val::Int))
+}
+
+pub fn list_to_vector(args: Args) -> Res {
+    Ok(Val::vector(args[0].unwrap_list()?.to_vec()))
+}
+
+pub fn vector_to_list(args: Args) -> Res {
+    Ok(Val::List(args[0].unwrap_vector()?.borrow().clone().into()))
+}
+
+pub fn list_to_set(args: Args) -> Res {
+    Ok(Val::Set(args[0].unwrap_list()?.iter().cloned().collect()))
+}
+
+pub fn set_to_list(args: Args) -> Res {
+    Ok(Val::List(args[0].unwrap_set()?.iter().cloned().collect::<Vec<_>>().into()))
+}
+
+pub const NAMES: &[&str] = &[
+    "char->integer", "integer->char", "number->string", "string->number", "list->vector",
+    "vector->list", "list->set", "set->list",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("char->integer", Arity::SomeArgs(1), char_to_integer);
+    e.register_func("integer->char", Arity::SomeArgs(1), integer_to_char);
+    e.register_func("number->string", Arity::SomeArgs(1), number_to_string);
+    e.register_func("string->number", Arity::SomeArgs(1), string_to_number);
+    e.register_func("list->vector", Arity::SomeArgs(1), list_to_vector);
+    e.register_func("vector->list", Arity::SomeArgs(1), vector_to_list);
+    e.register_func("list->set", Arity::SomeArgs(1), list_to_set);
+    e.register_func("set->list", Arity::SomeArgs(1), set_to_list);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ErrType;
+    use crate::types::Val;
+    use crate::stdlib::convert::*;
+
+    #[test]
+    fn test_char_integer() -> Result<(), ErrType> {
+        assert_eq!(char_to_integer(vec![Val::Char('a')])?, Val::Int(97));
+        assert_eq!(integer_to_char(vec![Val::Int(97)])?, Val::Char('a'));
+        assert!(integer_to_char(vec![Val::Int(-1)]).is_err());
+        assert!(integer_to_char(vec![Val::Int(0xd800)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_number_string() -> Result<(), ErrType> {
+        assert_eq!(number_to_string(vec![Val::Int(-12)])?, Val::string("-12"));
+        assert_eq!(string_to_number(vec![Val::string("42")])?, Val::Int(42));
+        assert_eq!(string_to_number(vec![Val::string("4x")])?, Val::Bool(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_collections() -> Result<(), ErrType> {
+        let l = Val::List(vec![Val::Int(1), Val::Int(2), Val::Int(1)].into());
+        let v = list_to_vector(vec![l.clone()])?;
+        assert_eq!(*v.unwrap_vector()?.borrow(), vec![Val::Int(1), Val::Int(2), Val::Int(1)]);
+        assert_eq!(vector_to_list(vec![v])?, l);
+        let s = list_to_set(vec![l])?;
+        assert_eq!(set_to_list(vec![s])?.unwrap_list()?.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use crate::env::{Arity, Env, Primitive};
+use crate::errors::ErrType;
+use crate::types::{Args, Res, Val};
+
+pub fn error(args: Args) -> Res {
+    let mut args = args.into_iter();
+    let msg = args.next().map_or(Ok(String::new()), |m| m.unwrap_string())?;
+    Err(ErrType::user(&msg, args.collect()))
+}
+
+pub fn raise(args: Args) -> Res {
+    match &args[0] {
+        Val::Error(e) => Err((**e).clone()),
+        v => Err(ErrType::raise(v.clone())),
+    }
+}
+
+pub fn error_object_p(args: Args) -> Res {
+    Ok(Val::Bool(args[0].unwrap_error().is_ok()))
+}
+
+pub fn error_object_message(args: Args) -> Res {
+    Ok(Val::string(&args[0].unwrap_error()?.message()))
+}
+
+pub fn error_object_irritants(args: Args) -> Res {
+    Ok(args[0].unwrap_error()?.irritants())
+}
+
+pub fn type_error_p(args: Args) -> Res {
+    let res = args[0].unwrap_error().map(|e| matches!(e.root(), ErrType::TypeError(_)));
+    Ok(Val::Bool(res.unwrap_or(false)))
+}
+
+pub const NAMES: &[&str] = &[
+    "error", "raise", "error-object?", "error-object-message", "error-object-irritants",
+    "type-error?", "invoke-restart",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("error", Arity::AtLeast(1), error);
+    e.register_func("raise", Arity::SomeArgs(1), raise);
+    e.register_func("error-object?", Arity::SomeArgs(1), error_object_p);
+    e.register_func("error-object-message", Arity::SomeArgs(1), error_object_message);
+    e.register_func("error-object-irritants", Arity::SomeArgs(1), error_object_irritants);
+    e.register_func("type-error?", Arity::SomeArgs(1), type_error_p);
+    e.register_primitive("invoke-restart", Primitive::InvokeRestart);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::errors::ErrType;
+    use crate::types::Val;
+    use crate::stdlib::error::*;
+
+    #[test]
+    fn test_error() {
+        let res = error(vec![Val::string("oops"), Val::Int(1)]);
+        assert_eq!(res, Err(ErrType::user("oops", vec![Val::Int(1)])));
+    }
+
+    #[test]
+    fn test_raise() {
+        assert_eq!(raise(vec![Val::Int(1)]), Err(ErrType::raise(Val::Int(1))));
+        let e = ErrType::lookup("a");
+        assert_eq!(raise(vec![Val::Error(Rc::new(e.clone()))]), Err(e));
+    }
+
+    #[test]
+    fn test_error_object_accessors() -> Result<(), ErrType> {
+        let e = Val::Error(Rc::new(ErrType::user("oops", vec![Val::Int(1)])));
+        assert_eq!(error_object_message(vec![e.clone()])?, Val::string("oops"));
+        assert_eq!(error_object_irritants(vec![e.clone()])?, Val::List(vec![Val::Int(1)].into()));
+        assert_eq!(type_error_p(vec![e])?, Val::Bool(false));
+        let e = Val::Error(Rc::new(ErrType::type_error("int", "list")));
+        assert_eq!(type_error_p(vec![e])?, Val::Bool(true));
+        assert_eq!(error_object_p(vec![Val::Int(1)])?, Val::Bool(false));
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+use crate::env::{Arity, Env};
+use crate::gc;
+use crate::symbol::Symbol;
+use crate::types::{Args, Res, Val};
+
+/// `(gc)` frees the values kept alive only by cycles among themselves, and
+/// returns how many objects it freed.
+pub fn gc(_: Args) -> Res {
+    Ok(Val::Int(gc::collect() as i64))
+}
+
+/// `(gc-stats)` returns a map of the objects alive now, the collections run
+/// and the objects they've freed, under `live`, `collections` and `freed`.
+pub fn gc_stats(_: Args) -> Res {
+    let stats = gc::stats();
+    let counts = [("live", stats.live), ("collections", stats.collections), ("freed", stats.freed)];
+    Ok(Val::Map(counts.iter().map(|(k, n)| (Val::Symbol(Symbol::intern(k)), Val::Int(*n as i64))).collect()))
+}
+
+pub const NAMES: &[&str] = &[
+    "gc", "gc-stats",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("gc", Arity::NoArgs, gc);
+    e.register_func("gc-stats", Arity::NoArgs, gc_stats);
+}
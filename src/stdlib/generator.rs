@@ -0,0 +1,113 @@
+//! Generators, written in Lisp on top of `shift` and `reset`. A generator
+//! runs a procedure of no arguments up to each `yield`, which captures the
+//! rest of the procedure as a continuation to carry on from next time.
+
+use crate::env::Env;
+use crate::eval::Evaluator;
+
+const SOURCE: &str = "
+(define-record-type generator (new-generator resume) generator? (resume generator-resume set-generator-resume!))
+(define-record-type yielded (make-yielded value k) yielded? (value yielded-value) (k yielded-k))
+(define-record-type generator-end (make-end) generator-end?)
+(define end (make-end))
+
+(define (yield v) (shift k (make-yielded v k)))
+
+(define (make-generator proc)
+  (new-generator (lambda (_) (proc) end)))
+
+(define (generator-next g)
+  (let ((r (reset ((generator-resume g) '()))))
+    (cond ((yielded? r)
+           (set-generator-resume! g (yielded-k r))
+           (yielded-value r))
+          (else
+           (set-generator-resume! g (lambda (_) end))
+           end))))
+
+(define (for-each f g)
+  (let ((v (generator-next g)))
+    (unless (generator-end? v)
+      (f v)
+      (for-each f g))))
+";
+
+/// `(make-generator proc)` makes a generator of the values `proc` passes to
+/// `(yield v)`. `(generator-next g)` runs `g` up to its next `yield` and
+/// returns the value yielded, or once `proc` returns, the end marker
+/// `generator-end?` recognizes. `(for-each f g)` calls `f` with each value
+/// `g` yields.
+pub const NAMES: &[&str] = &[
+    "make-generator", "yield", "generator-next", "generator?", "generator-end?", "for-each",
+];
+
+/// Evaluates the definitions in an environment of their own, so they see
+/// none of `e`'s bindings, and binds the procedures named in `NAMES` in `e`.
+pub fn register(e: &Env) {
+    let mut defs = Env::new(None);
+    let mut ev = Evaluator::new();
+    ev.eval_str(SOURCE, "generator", &mut defs).expect("the generator definitions evaluate");
+    for name in NAMES {
+        let v = ev.eval_str(name, "generator", &mut defs).expect("the generator definitions bind NAMES");
+        e.register(name, v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::env::Env;
+    use crate::errors::ErrType;
+    use crate::eval::Evaluator;
+    use crate::stdlib::core::core;
+    use crate::types::{Res, Val};
+
+    fn eval(src: &str) -> Res {
+        let core = core();
+        let mut env = Env::new(Some(&core));
+        Evaluator::new().eval_str(src, "test", &mut env)
+    }
+
+    #[test]
+    fn test_generator_next() -> Result<(), ErrType> {
+        let src = "(define (count) (yield 1) (yield 2) 'ignored)
+                   (define g (make-generator count))
+                   (define a (generator-next g))
+                   (define b (generator-next g))
+                   (list a b (generator-end? (generator-next g)) (generator-end? (generator-next g)))";
+        assert_eq!(eval(src)?, eval("'(1 2 #t #t)")?);
+        assert_eq!(eval("(generator? (make-generator (lambda () 1)))")?, Val::Bool(true));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generator_resumes_after_yield() -> Result<(), ErrType> {
+        // The value of each yield is that of the local x, so the generator
+        // must carry on from inside the loop rather than start over.
+        let src = "(define (from n)
+                     (lambda ()
+                       (define (loop x) (yield x) (loop (+ x 1)))
+                       (loop n)))
+                   (define g (make-generator (from 5)))
+                   (define a (generator-next g))
+                   (define b (generator-next g))
+                   (list a b (generator-next g))";
+        assert_eq!(eval(src)?, eval("'(5 6 7)")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_each() -> Result<(), ErrType> {
+        let src = "(define (walk tree)
+                     (match tree
+                       ((l r) (walk l) (walk r))
+                       (leaf (yield leaf))))
+                   (define seen (vector (pvector)))
+                   (define (see x) (vector-set! seen 0 (conj (vector-ref seen 0) x)))
+                   (for-each see (make-generator (lambda () (walk '((1 2) (3 (4 5)))))))
+                   (vector-ref seen 0)";
+        assert_eq!(eval(src)?, eval("(pvector 1 2 3 4 5)")?);
+        let err = eval("(yield 1)").unwrap_err();
+        assert_eq!(err.root(), &ErrType::user("shift outside of reset", vec![]));
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use crate::env::{Arity, Env, Primitive};
+use crate::errors::ErrType;
+use crate::types::{Args, Res, Val};
+
+/// Strings display as their text, everything else as it prints. The empty
+/// string and the empty list are the same value, and display as nothing.
+fn text(v: &Val) -> String {
+    match v.unwrap_string() {
+        Ok(s) => s,
+        _ => v.to_string(),
+    }
+}
+
+fn io_error(e: io::Error, irritants: Vec<Val>) -> ErrType {
+    ErrType::user(&e.to_string(), irritants)
+}
+
+pub fn display(args: Args) -> Res {
+    print!("{}", text(&args[0]));
+    io::stdout().flush().map_err(|e| io_error(e, vec![]))?;
+    Ok(Val::Nil)
+}
+
+pub fn newline(_: Args) -> Res {
+    println!();
+    Ok(Val::Nil)
+}
+
+/// `(read-line)` reads a line from stdin without its newline, returning `#f`
+/// at end of input.
+pub fn read_line(_: Args) -> Res {
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) => Ok(Val::Bool(false)),
+        Ok(_) => Ok(Val::string(line.trim_end_matches(&['\r', '\n'][..]))),
+        Err(e) => Err(io_error(e, vec![])),
+    }
+}
+
+pub fn read_file(args: Args) -> Res {
+    let path = args[0].unwrap_string()?;
+    fs::read_to_string(&path).map(|s| Val::string(&s)).map_err(|e| io_error(e, args))
+}
+
+pub fn write_file(args: Args) -> Res {
+    let path = args[0].unwrap_string()?;
+    let contents = text(&args[1]);
+    fs::write(&path, contents).map_err(|e| io_error(e, vec![args[0].clone()]))?;
+    Ok(Val::Nil)
+}
+
+pub const NAMES: &[&str] = &[
+    "display", "newline", "read-line", "read-file", "write-file", "load",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("display", Arity::SomeArgs(1), display);
+    e.register_func("newline", Arity::NoArgs, newline);
+    e.register_func("read-line", Arity::NoArgs, read_line);
+    e.register_func("read-file", Arity::SomeArgs(1), read_file);
+    e.register_func("write-file", Arity::SomeArgs(2), write_file);
+    e.register_primitive("load", Primitive::Load);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ErrType;
+    use crate::types::Val;
+    use crate::stdlib::io::*;
+
+    #[test]
+    fn test_write_read_file() -> Result<(), ErrType> {
+        let path = std::env::temp_dir().join(format!("lisp-io-{}", std::process::id()));
+        let path = Val::string(&path.to_string_lossy());
+        assert_eq!(write_file(vec![path.clone(), Val::string("hi\n")])?, Val::Nil);
+        assert_eq!(read_file(vec![path.clone()])?, Val::string("hi\n"));
+        fs::remove_file(path.unwrap_string()?).unwrap();
+        assert!(read_file(vec![path]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(text(&Val::string("a b")), "a b");
+        assert_eq!(text(&Val::string("")), "");
+        assert_eq!(text(&Val::Int(1)), "1");
+    }
+}
@@ -1,57 +1,62 @@
+use crate::env::{Arity, Env};
 use crate::types::{Args, Res, Val};
 
+/// `car` and `cdr` of `()` are errors, as there's no element to take.
 pub fn car(args: Args) -> Res {
-    let l = args[0].unwrap_list()?;
-    if l.len() > 0 {
-        Ok(l[0].clone())
-    } else {
-        Ok(Val::List(Vec::new()))
-    }
+    let l = args[0].unwrap_pair()?;
+    Ok(l[0].clone())
 }
 
 pub fn cdr(args: Args) -> Res {
-    let l = args[0].unwrap_list()?;
-    if l.len() > 1 {
-        let mut l2 = Vec::new();
-        for v in l[1..].iter() {
-            l2.push(v.clone());
-        }
-        Ok(Val::List(l2))
-    } else {
-        Ok(Val::List(Vec::new()))
-    }
+    let l = args[0].unwrap_pair()?;
+    Ok(Val::List(l.rest()))
+}
+
+pub fn null_p(args: Args) -> Res {
+    Ok(Val::Bool(matches!(&args[0], Val::List(l) if l.is_empty())))
+}
+
+pub fn nil_p(args: Args) -> Res {
+    Ok(Val::Bool(args[0] == Val::Nil))
 }
 
 pub fn list(args: Args) -> Res {
-    let mut l = Vec::new();
-    for v in args.iter() {
-        l.push(v.clone());
-    }
-    Ok(Val::List(l))
+    Ok(Val::List(args.into()))
+}
+
+pub const NAMES: &[&str] = &[
+    "car", "cdr", "list", "null?", "nil?",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("car", Arity::SomeArgs(1), car);
+    e.register_func("cdr", Arity::SomeArgs(1), cdr);
+    e.register_func("list", Arity::VarArgs, list);
+    e.register_func("null?", Arity::SomeArgs(1), null_p);
+    e.register_func("nil?", Arity::SomeArgs(1), nil_p);
 }
 
 #[cfg(test)]
 mod tests {
     use crate::errors::ErrType;
-    use crate::types::Val;
+    use crate::types::{List, Val};
     use crate::stdlib::list::*;
 
     #[test]
     fn test_car() -> Result<(), ErrType> {
         let a = Val::Char('a');
         let b = Val::Int(1);
-        let l = Val::List(vec![a, b]);
+        let l = Val::List(vec![a, b].into());
         let res = car(vec![l])?;
         assert_eq!(res, Val::Char('a'));
         Ok(())
     }
 
     #[test]
-    fn test_car_short() -> Result<(), ErrType> {
-        let l = Val::List(Vec::new());
-        let res = car(vec![l])?;
-        assert_eq!(res, Val::List(Vec::new()));
-        Ok(())
+    fn test_car_short() {
+        let l = Val::List(List::new());
+        let res = car(vec![l]);
+        assert_eq!(res, Err(ErrType::type_error("non-empty list", "empty list")));
     }
 
     #[test]
@@ -59,8 +64,8 @@ mod tests {
         let a = Val::Char('a');
         let b = Val::Char('b');
         let c = Val::Char('c');
-        let l = Val::List(vec![a, b, c]);
-        let expected = Val::List(vec![Val::Char('b'), Val::Char('c')]);
+        let l = Val::List(vec![a, b, c].into());
+        let expected = Val::List(vec![Val::Char('b'), Val::Char('c')].into());
         let res = cdr(vec![l])?;
         assert_eq!(res, expected);
         Ok(())
@@ -68,9 +73,20 @@ mod tests {
 
     #[test]
     fn test_cdr_short() -> Result<(), ErrType> {
-        let l = Val::List(Vec::new());
+        let l = Val::List(vec![Val::Int(1)].into());
         let res = cdr(vec![l])?;
-        assert_eq!(res, Val::List(Vec::new()));
+        assert_eq!(res, Val::List(List::new()));
+        assert!(cdr(vec![res]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_nil() -> Result<(), ErrType> {
+        let empty = Val::List(List::new());
+        assert_eq!(null_p(vec![empty.clone()])?, Val::Bool(true));
+        assert_eq!(null_p(vec![Val::Nil])?, Val::Bool(false));
+        assert_eq!(nil_p(vec![Val::Nil])?, Val::Bool(true));
+        assert_eq!(nil_p(vec![empty])?, Val::Bool(false));
         Ok(())
     }
 
@@ -78,8 +94,22 @@ mod tests {
     fn test_list() -> Result<(), ErrType> {
         let l = vec![Val::Char('a'), Val::Char('b')];
         let l2 = list(l)?;
-        let expected = Val::List(vec![Val::Char('a'), Val::Char('b')]);
+        let expected = Val::List(vec![Val::Char('a'), Val::Char('b')].into());
         assert_eq!(l2, expected);
         Ok(())
     }
+
+    #[test]
+    fn test_cdr_long_list() -> Result<(), ErrType> {
+        let n = 100_000;
+        let mut l = list((0..n).map(Val::Int).collect())?;
+        let mut sum = 0;
+        for _ in 0..n {
+            sum += car(vec![l.clone()])?.unwrap_int()?;
+            l = cdr(vec![l])?;
+        }
+        assert_eq!(sum, (0..n).sum::<i64>());
+        assert_eq!(l, Val::List(List::new()));
+        Ok(())
+    }
 }
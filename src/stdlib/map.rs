@@ -0,0 +1,142 @@
+use std::convert::TryFrom;
+
+use crate::env::{Arity, Env};
+use crate::errors::ErrType;
+use crate::persistent::PMap;
+use crate::types::{Args, Res, Val};
+
+pub fn hash_map(args: Args) -> Res {
+    if !args.len().is_multiple_of(2) {
+        return Err(ErrType::user("hash-map needs an even number of arguments", args));
+    }
+    let mut args = args.into_iter();
+    let mut m = PMap::new();
+    while let (Some(k), Some(v)) = (args.next(), args.next()) {
+        m = m.insert(k, v);
+    }
+    Ok(Val::Map(m))
+}
+
+/// `(get coll k [default])` looks `k` up in a map, or indexes a pvector,
+/// returning `default`, or `#f`, when `k` is absent.
+pub fn get(args: Args) -> Res {
+    let default = args.get(2).cloned().unwrap_or(Val::Bool(false));
+    let found = match &args[0] {
+        Val::PVector(v) => {
+            let i = *args[1].unwrap_int()?;
+            usize::try_from(i).ok().and_then(|i| v.get(i))
+        }
+        m => m.unwrap_map()?.get(&args[1]),
+    };
+    Ok(found.cloned().unwrap_or(default))
+}
+
+/// `(assoc coll k v)` binds `k` in a map, or replaces index `k` of a pvector.
+pub fn assoc(args: Args) -> Res {
+    match &args[0] {
+        Val::PVector(v) => {
+            let i = *args[1].unwrap_int()?;
+            let set = usize::try_from(i).ok().and_then(|u| v.set(u, args[2].clone()));
+            Ok(Val::PVector(set.ok_or_else(|| ErrType::index(i, v.len()))?))
+        }
+        m => Ok(Val::Map(m.unwrap_map()?.insert(args[1].clone(), args[2].clone()))),
+    }
+}
+
+pub fn dissoc(args: Args) -> Res {
+    let m = args[0].unwrap_map()?;
+    Ok(Val::Map(m.remove(&args[1])))
+}
+
+pub fn keys(args: Args) -> Res {
+    let m = args[0].unwrap_map()?;
+    Ok(Val::List(m.keys().cloned().collect::<Vec<_>>().into()))
+}
+
+pub fn vals(args: Args) -> Res {
+    let m = args[0].unwrap_map()?;
+    Ok(Val::List(m.values().cloned().collect::<Vec<_>>().into()))
+}
+
+pub fn pvector(args: Args) -> Res {
+    Ok(Val::PVector(args.into_iter().collect()))
+}
+
+/// `(conj v x ...)` appends to a pvector.
+pub fn conj(args: Args) -> Res {
+    let v = args[0].unwrap_pvector()?;
+    Ok(Val::PVector(args[1..].iter().fold(v.clone(), |v, x| v.push(x.clone()))))
+}
+
+pub const NAMES: &[&str] = &[
+    "hash-map", "get", "assoc", "dissoc", "keys", "vals", "pvector", "conj",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("hash-map", Arity::VarArgs, hash_map);
+    e.register_func("get", Arity::Range(2, 3), get);
+    e.register_func("assoc", Arity::SomeArgs(3), assoc);
+    e.register_func("dissoc", Arity::SomeArgs(2), dissoc);
+    e.register_func("keys", Arity::SomeArgs(1), keys);
+    e.register_func("vals", Arity::SomeArgs(1), vals);
+    e.register_func("pvector", Arity::VarArgs, pvector);
+    e.register_func("conj", Arity::AtLeast(1), conj);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ErrType;
+    use crate::types::Val;
+    use crate::stdlib::map::*;
+
+    fn sample() -> Res {
+        hash_map(vec![Val::Int(1), Val::Char('a'), Val::Char('b'), Val::Int(2)])
+    }
+
+    #[test]
+    fn test_get() -> Result<(), ErrType> {
+        let m = sample()?;
+        assert_eq!(get(vec![m.clone(), Val::Int(1)])?, Val::Char('a'));
+        assert_eq!(get(vec![m.clone(), Val::Int(3)])?, Val::Bool(false));
+        assert_eq!(get(vec![m, Val::Int(3), Val::Int(0)])?, Val::Int(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_assoc_dissoc() -> Result<(), ErrType> {
+        let m = sample()?;
+        let m2 = assoc(vec![m.clone(), Val::Int(3), Val::Int(4)])?;
+        assert_eq!(get(vec![m2.clone(), Val::Int(3)])?, Val::Int(4));
+        assert_eq!(get(vec![m.clone(), Val::Int(3)])?, Val::Bool(false));
+        let m3 = dissoc(vec![m2, Val::Int(3)])?;
+        assert_eq!(m3, m);
+        Ok(())
+    }
+
+    #[test]
+    fn test_keys_vals() -> Result<(), ErrType> {
+        let m = hash_map(vec![Val::Int(1), Val::Int(2)])?;
+        assert_eq!(keys(vec![m.clone()])?, Val::List(vec![Val::Int(1)].into()));
+        assert_eq!(vals(vec![m])?, Val::List(vec![Val::Int(2)].into()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_map_odd_args() {
+        assert!(hash_map(vec![Val::Int(1)]).is_err());
+    }
+
+    #[test]
+    fn test_pvector() -> Result<(), ErrType> {
+        let v = pvector(vec![Val::Int(1)])?;
+        let v2 = conj(vec![v.clone(), Val::Int(2), Val::Int(3)])?;
+        assert_eq!(v2, pvector(vec![Val::Int(1), Val::Int(2), Val::Int(3)])?);
+        let v3 = assoc(vec![v2.clone(), Val::Int(0), Val::Int(0)])?;
+        assert_eq!(get(vec![v3, Val::Int(0)])?, Val::Int(0));
+        assert_eq!(get(vec![v2.clone(), Val::Int(0)])?, Val::Int(1));
+        assert_eq!(get(vec![v2.clone(), Val::Int(5)])?, Val::Bool(false));
+        assert_eq!(assoc(vec![v2, Val::Int(3), Val::Int(0)]), Err(ErrType::index(3, 3)));
+        assert_eq!(v.to_string(), "[1]");
+        Ok(())
+    }
+}
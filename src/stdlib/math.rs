@@ -1,5 +1,33 @@
+use crate::env::{Arity, Env};
+use crate::errors::ErrType;
 use crate::types::{Args, Res, Val};
 
+// Integers are 64 bits, and arithmetic on them is checked: overflowing, or
+// dividing by zero, raises an ArithmeticError rather than panicking.
+
+fn overflow(op: &str) -> ErrType {
+    ErrType::arithmetic(op, "integer overflow")
+}
+
+fn add(a: i64, b: i64) -> Result<i64, ErrType> {
+    a.checked_add(b).ok_or_else(|| overflow("+"))
+}
+
+fn sub(a: i64, b: i64) -> Result<i64, ErrType> {
+    a.checked_sub(b).ok_or_else(|| overflow("-"))
+}
+
+fn mul(a: i64, b: i64) -> Result<i64, ErrType> {
+    a.checked_mul(b).ok_or_else(|| overflow("*"))
+}
+
+fn div(a: i64, b: i64) -> Result<i64, ErrType> {
+    if b == 0 {
+        return Err(ErrType::arithmetic("/", "division by zero"));
+    }
+    a.checked_div(b).ok_or_else(|| overflow("/"))
+}
+
 pub fn add_0(_: Args) -> Res {
     Ok(Val::Int(0))
 }
@@ -7,17 +35,16 @@ pub fn add_0(_: Args) -> Res {
 pub fn add_2(args: Args) -> Res {
     let a = args[0].unwrap_int()?;
     let b = args[1].unwrap_int()?;
-    Ok(Val::Int(a + b))
+    Ok(Val::Int(add(*a, *b)?))
 }
 
 pub fn add_varargs(args: Args) -> Res {
-    args.into_iter().fold(
-        Ok(Val::Int(0)),
+    args.into_iter().try_fold(
+        Val::Int(0),
         |acc, x| {
-            let a = acc.unwrap();
-            let a = a.unwrap_int()?;
+            let a = acc.unwrap_int()?;
             let x = x.unwrap_int()?;
-            Ok(Val::Int(a + x))
+            Ok(Val::Int(add(*a, *x)?))
         }
     )
 }
@@ -28,13 +55,13 @@ pub fn sub_0(_: Args) -> Res {
 
 pub fn sub_1(args: Args) -> Res {
     let a = args[0].unwrap_int()?;
-    Ok(Val::Int(a * -1))
+    Ok(Val::Int(a.checked_neg().ok_or_else(|| overflow("-"))?))
 }
 
 pub fn sub_2(args: Args) -> Res {
     let a = args[0].unwrap_int()?;
     let b = args[1].unwrap_int()?;
-    Ok(Val::Int(a - b))
+    Ok(Val::Int(sub(*a, *b)?))
 }
 
 pub fn sub_varargs(args: Args) -> Res {
@@ -44,7 +71,7 @@ pub fn sub_varargs(args: Args) -> Res {
         if i == 0 {
             val = *v;
         } else {
-            val = val - v;
+            val = sub(val, *v)?;
         }
     }
     Ok(Val::Int(val))
@@ -57,17 +84,16 @@ pub fn mul_0(_: Args) -> Res {
 pub fn mul_2(args: Args) -> Res {
     let a = args[0].unwrap_int()?;
     let b = args[1].unwrap_int()?;
-    Ok(Val::Int(a * b))
+    Ok(Val::Int(mul(*a, *b)?))
 }
 
 pub fn mul_varargs(args: Args) -> Res {
-    args.into_iter().fold(
-        Ok(Val::Int(1)),
+    args.into_iter().try_fold(
+        Val::Int(1),
         |acc, x| {
-            let a = acc.unwrap();
-            let a = a.unwrap_int()?;
+            let a = acc.unwrap_int()?;
             let x = x.unwrap_int()?;
-            Ok(Val::Int(a * x))
+            Ok(Val::Int(mul(*a, *x)?))
         }
     )
 }
@@ -79,7 +105,7 @@ pub fn div_0(_: Args) -> Res {
 pub fn div_2(args: Args) -> Res {
     let a = args[0].unwrap_int()?;
     let b = args[1].unwrap_int()?;
-    Ok(Val::Int(a / b))
+    Ok(Val::Int(div(*a, *b)?))
 }
 
 pub fn div_varargs(args: Args) -> Res {
@@ -89,12 +115,31 @@ pub fn div_varargs(args: Args) -> Res {
         if i == 0 {
             val = *v;
         } else {
-            val = val / v;
+            val = div(val, *v)?;
         }
     }
     Ok(Val::Int(val))
 }
 
+pub const NAMES: &[&str] = &[
+    "+", "-", "*", "/",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("+", Arity::NoArgs, add_0);
+    e.register_func("+", Arity::SomeArgs(2), add_2);
+    e.register_func("+", Arity::VarArgs, add_varargs);
+    e.register_func("-", Arity::NoArgs, sub_0);
+    e.register_func("-", Arity::SomeArgs(1), sub_1);
+    e.register_func("-", Arity::AtLeast(2), sub_varargs);
+    e.register_func("*", Arity::NoArgs, mul_0);
+    e.register_func("*", Arity::SomeArgs(2), mul_2);
+    e.register_func("*", Arity::VarArgs, mul_varargs);
+    e.register_func("/", Arity::NoArgs, div_0);
+    e.register_func("/", Arity::SomeArgs(2), div_2);
+    e.register_func("/", Arity::VarArgs, div_varargs);
+}
+
 #[cfg(test)]
 mod test {
     use crate::errors::ErrType;
@@ -171,4 +216,20 @@ mod test {
         assert_eq!(res, Val::Int(2));
         Ok(())
     }
+
+    #[test]
+    fn test_arithmetic_errors() {
+        let max = Val::Int(i64::MAX);
+        let min = Val::Int(i64::MIN);
+        assert_eq!(div_2(vec![Val::Int(1), Val::Int(0)]), Err(ErrType::arithmetic("/", "division by zero")));
+        assert_eq!(div_2(vec![min.clone(), Val::Int(-1)]), Err(ErrType::arithmetic("/", "integer overflow")));
+        assert!(div_varargs(vec![Val::Int(1), Val::Int(2), Val::Int(0)]).is_err());
+        assert_eq!(mul_2(vec![max.clone(), Val::Int(2)]), Err(ErrType::arithmetic("*", "integer overflow")));
+        assert!(mul_varargs(vec![max.clone(), Val::Int(1), Val::Int(2)]).is_err());
+        assert!(add_2(vec![max.clone(), Val::Int(1)]).is_err());
+        assert!(add_varargs(vec![max, Val::Int(1), Val::Int(0)]).is_err());
+        assert_eq!(sub_1(vec![min.clone()]), Err(ErrType::arithmetic("-", "integer overflow")));
+        assert!(sub_2(vec![min.clone(), Val::Int(1)]).is_err());
+        assert!(sub_varargs(vec![min, Val::Int(1), Val::Int(0)]).is_err());
+    }
 }
@@ -1,30 +1,172 @@
+//! The builtins, grouped into modules. Each module has a `register` function
+//! adding its builtins to an environment, and lists the names that binds in
+//! `NAMES`; `Builder` assembles an environment from a chosen set of them. The `io` module, which reads and writes files
+//! and the terminal, is only compiled with the `io` feature.
+
+pub mod convert;
+pub mod error;
+pub mod gc;
+pub mod generator;
+#[cfg(feature = "io")]
+pub mod io;
 pub mod list;
+pub mod map;
 pub mod math;
+pub mod set;
+pub mod string;
+pub mod symbol;
+pub mod types;
+pub mod vector;
 
+use crate::env::Env;
+use crate::errors::ErrType;
 
-pub mod core {
-    use crate::env::{Arity, Env};
-    use super::list;
-    use super::math;
-
-    pub fn core() -> Env<'static> {
-        let mut e = Env::new(None);
-        e.register_func("+", Arity::NoArgs, math::add_0);
-        e.register_func("+", Arity::SomeArgs(2), math::add_2);
-        e.register_func("+", Arity::VarArgs, math::add_varargs);
-        e.register_func("-", Arity::NoArgs, math::sub_0);
-        e.register_func("-", Arity::SomeArgs(1), math::sub_1);
-        e.register_func("-", Arity::SomeArgs(2), math::sub_2);
-        e.register_func("-", Arity::VarArgs, math::sub_varargs);
-        e.register_func("*", Arity::NoArgs, math::mul_0);
-        e.register_func("*", Arity::SomeArgs(2), math::mul_2);
-        e.register_func("*", Arity::VarArgs, math::mul_varargs);
-        e.register_func("/", Arity::NoArgs, math::div_0);
-        e.register_func("/", Arity::SomeArgs(2), math::div_2);
-        e.register_func("/", Arity::VarArgs, math::div_varargs);
-        e.register_func("car", Arity::SomeArgs(1), list::car);
-        e.register_func("cdr", Arity::SomeArgs(1), list::cdr);
-        e.register_func("list", Arity::VarArgs, list::list);
+/// The `register` function of a module.
+pub type Register = fn(&Env);
+
+/// Every module compiled in: its name, its `register` function, and the
+/// names that binds.
+pub const MODULES: &[(&str, Register, &[&str])] = &[
+    ("math", math::register, math::NAMES),
+    ("list", list::register, list::NAMES),
+    ("string", string::register, string::NAMES),
+    ("symbol", symbol::register, symbol::NAMES),
+    ("vector", vector::register, vector::NAMES),
+    ("map", map::register, map::NAMES),
+    ("set", set::register, set::NAMES),
+    ("types", types::register, types::NAMES),
+    ("convert", convert::register, convert::NAMES),
+    ("error", error::register, error::NAMES),
+    ("generator", generator::register, generator::NAMES),
+    ("gc", gc::register, gc::NAMES),
+    #[cfg(feature = "io")]
+    ("io", io::register, io::NAMES),
+];
+
+/// Modules that reach outside the interpreter, left out of sandboxes.
+const UNSANDBOXED: &[&str] = &["io"];
+
+/// Builds a root environment holding the builtins of the chosen modules.
+#[derive(Default)]
+pub struct Builder {
+    /// Each module's `register` function, and the names it binds if known.
+    modules: Vec<(Register, Option<&'static [&'static str]>)>,
+    lazy: bool,
+}
+
+impl Builder {
+    /// A builder with no modules chosen.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// A builder with every module in `MODULES` chosen.
+    pub fn all() -> Self {
+        Builder { modules: MODULES.iter().map(|(_, r, names)| (*r, Some(*names))).collect(), lazy: false }
+    }
+    /// A builder with every module chosen except those touching files or the
+    /// terminal, for running untrusted code.
+    pub fn sandbox() -> Self {
+        let safe = MODULES.iter().filter(|(name, ..)| !UNSANDBOXED.contains(name));
+        Builder { modules: safe.map(|(_, r, names)| (*r, Some(*names))).collect(), lazy: false }
+    }
+    /// Chooses a module by its `register` function. Its names aren't known,
+    /// so a lazy environment registers it straight away.
+    pub fn with(mut self, register: Register) -> Self {
+        self.modules.push((register, None));
+        self
+    }
+    /// Chooses the module called `name` in `MODULES`, failing if there isn't
+    /// one, say because its feature is disabled.
+    pub fn with_named(mut self, name: &str) -> Result<Self, ErrType> {
+        match MODULES.iter().find(|(n, ..)| *n == name) {
+            Some((_, r, names)) => {
+                self.modules.push((*r, Some(*names)));
+                Ok(self)
+            }
+            None => Err(ErrType::lookup(name)),
+        }
+    }
+    /// Makes the environment register each module only once one of its names
+    /// is looked up; see `Env::lazy`.
+    pub fn lazy(mut self) -> Self {
+        self.lazy = true;
+        self
+    }
+    pub fn build(self) -> Env {
+        if !self.lazy {
+            let e = Env::new(None);
+            for (register, _) in self.modules {
+                register(&e);
+            }
+            return e;
+        }
+        let named = self.modules.iter().filter_map(|(r, names)| names.map(|names| (*r, names)));
+        let e = Env::lazy(named.collect());
+        for (register, _) in self.modules.iter().filter(|(_, names)| names.is_none()) {
+            register(&e);
+        }
         e
     }
 }
+
+pub mod core {
+    use crate::env::Env;
+    use super::Builder;
+
+    /// An environment with every builtin compiled in.
+    pub fn core() -> Env {
+        Builder::all().build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol::Symbol;
+    use crate::types::Val;
+
+    #[test]
+    fn test_builder() -> Result<(), ErrType> {
+        let e = Builder::new().with(math::register).with_named("list")?.build();
+        assert!(e.lookup("+").is_ok());
+        assert!(e.lookup("car").is_ok());
+        assert_eq!(e.lookup("vector").unwrap_err(), ErrType::lookup("vector"));
+        assert!(Builder::new().with_named("nope").is_err());
+        assert!(core::core().lookup("set-add").is_ok());
+        let sandbox = Builder::sandbox().build();
+        assert!(sandbox.lookup("set-add").is_ok());
+        assert!(sandbox.lookup("read-file").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_lazy() -> Result<(), ErrType> {
+        let defines = |e: &Env, name: &str| e.defines(Symbol::intern(name));
+        let e = Builder::new().with_named("math")?.with_named("list")?.with_named("string")?.lazy().build();
+        assert_eq!(e.lookup("vector").unwrap_err(), ErrType::lookup("vector"));
+        assert!(!defines(&e, "+") && !defines(&e, "car"));
+        assert!(e.lookup("string-length").is_ok());
+        assert!(!defines(&e, "+") && !defines(&e, "car"));
+        e.register("car", Val::Int(1));
+        assert!(e.lookup("list").is_ok());
+        assert!(!defines(&e, "+"));
+        assert_eq!(e.lookup("car")?.unwrap_val(), Some(&Val::Int(1)));
+        // A module chosen by its register function alone is registered at once.
+        let e = Builder::new().with(math::register).with_named("list")?.lazy().build();
+        assert!(defines(&e, "+") && !defines(&e, "car"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_module_names() {
+        for (module, register, names) in MODULES {
+            let e = Env::new(None);
+            register(&e);
+            let mut bound: Vec<&str> = e.names().iter().map(|s| s.as_str()).collect();
+            let mut listed = names.to_vec();
+            bound.sort_unstable();
+            listed.sort_unstable();
+            assert_eq!(bound, listed, "{}", module);
+        }
+    }
+}
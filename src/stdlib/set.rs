@@ -0,0 +1,111 @@
+use crate::env::{Arity, Env};
+use crate::persistent::PSet;
+use crate::types::{Args, Res, Val};
+
+pub fn set(args: Args) -> Res {
+    Ok(Val::Set(args.into_iter().collect()))
+}
+
+/// `(set-add s x ...)` returns `s` with every `x` added.
+pub fn set_add(args: Args) -> Res {
+    let s = args[0].unwrap_set()?;
+    Ok(Val::Set(args[1..].iter().fold(s.clone(), |s, x| s.insert(x.clone()))))
+}
+
+/// `(set-remove s x ...)` returns `s` without any of the `x`s.
+pub fn set_remove(args: Args) -> Res {
+    let s = args[0].unwrap_set()?;
+    Ok(Val::Set(args[1..].iter().fold(s.clone(), |s, x| s.remove(x))))
+}
+
+pub fn set_member_p(args: Args) -> Res {
+    Ok(Val::Bool(args[0].unwrap_set()?.contains(&args[1])))
+}
+
+pub fn union(args: Args) -> Res {
+    let mut acc = PSet::new();
+    for s in args.iter() {
+        acc = acc.union(s.unwrap_set()?);
+    }
+    Ok(Val::Set(acc))
+}
+
+pub fn intersection(args: Args) -> Res {
+    let mut acc = args[0].unwrap_set()?.clone();
+    for s in args[1..].iter() {
+        acc = acc.intersection(s.unwrap_set()?);
+    }
+    Ok(Val::Set(acc))
+}
+
+/// `(difference s t ...)` is the elements of `s` in none of the `t`s.
+pub fn difference(args: Args) -> Res {
+    let mut acc = args[0].unwrap_set()?.clone();
+    for s in args[1..].iter() {
+        acc = acc.difference(s.unwrap_set()?);
+    }
+    Ok(Val::Set(acc))
+}
+
+pub fn subset_p(args: Args) -> Res {
+    Ok(Val::Bool(args[0].unwrap_set()?.is_subset(args[1].unwrap_set()?)))
+}
+
+pub const NAMES: &[&str] = &[
+    "set", "set-add", "set-remove", "set-member?", "union", "intersection", "difference", "subset?",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("set", Arity::VarArgs, set);
+    e.register_func("set-add", Arity::AtLeast(1), set_add);
+    e.register_func("set-remove", Arity::AtLeast(1), set_remove);
+    e.register_func("set-member?", Arity::SomeArgs(2), set_member_p);
+    e.register_func("union", Arity::VarArgs, union);
+    e.register_func("intersection", Arity::AtLeast(1), intersection);
+    e.register_func("difference", Arity::AtLeast(1), difference);
+    e.register_func("subset?", Arity::SomeArgs(2), subset_p);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ErrType;
+    use crate::symbol::Symbol;
+    use crate::types::Val;
+    use crate::stdlib::set::*;
+
+    fn ints(xs: &[i64]) -> Val {
+        Val::Set(xs.iter().copied().map(Val::Int).collect())
+    }
+
+    #[test]
+    fn test_set_dedupes() -> Result<(), ErrType> {
+        let a = Val::Symbol(Symbol::intern("a"));
+        let b = Val::Symbol(Symbol::intern("b"));
+        let s = set(vec![a.clone(), b.clone(), a.clone()])?;
+        assert_eq!(s.unwrap_set()?.len(), 2);
+        assert_eq!(set_member_p(vec![s.clone(), b.clone()])?, Val::Bool(true));
+        let s = set_remove(vec![s, b.clone()])?;
+        assert_eq!(set_member_p(vec![s.clone(), b.clone()])?, Val::Bool(false));
+        assert_eq!(set_add(vec![s, b, a.clone(), a])?.unwrap_set()?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_ops() -> Result<(), ErrType> {
+        let a = ints(&[1, 2, 3]);
+        let b = ints(&[2, 3, 4]);
+        assert_eq!(union(vec![a.clone(), b.clone()])?, ints(&[1, 2, 3, 4]));
+        assert_eq!(union(vec![])?, ints(&[]));
+        assert_eq!(intersection(vec![a.clone(), b.clone(), ints(&[3])])?, ints(&[3]));
+        assert_eq!(difference(vec![a.clone(), b.clone()])?, ints(&[1]));
+        assert_eq!(subset_p(vec![ints(&[2, 3]), a.clone()])?, Val::Bool(true));
+        assert_eq!(subset_p(vec![b, a.clone()])?, Val::Bool(false));
+        assert!(union(vec![a, Val::Int(1)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_display_set() {
+        assert_eq!(ints(&[1]).to_string(), "#{1}");
+    }
+}
@@ -0,0 +1,151 @@
+use std::convert::TryFrom;
+
+use crate::env::{Arity, Env};
+use crate::errors::ErrType;
+use crate::types::{Args, Res, Val};
+
+fn chars(v: &Val) -> Result<Vec<char>, ErrType> {
+    Ok(v.unwrap_string()?.chars().collect())
+}
+
+/// Checks that `i` is a position in a string of `len` chars, counting the
+/// end as one.
+fn position(i: &Val, len: usize) -> Result<usize, ErrType> {
+    let i = *i.unwrap_int()?;
+    usize::try_from(i).ok().filter(|&u| u <= len).ok_or_else(|| ErrType::index(i, len))
+}
+
+/// Strings are lists of chars, so `(string? '())` is true.
+pub fn string_p(args: Args) -> Res {
+    Ok(Val::Bool(args[0].unwrap_string().is_ok()))
+}
+
+pub fn string_length(args: Args) -> Res {
+    Ok(Val::Int(chars(&args[0])?.len() as i64))
+}
+
+pub fn string_ref(args: Args) -> Res {
+    let s = chars(&args[0])?;
+    match position(&args[1], s.len())? {
+        i if i < s.len() => Ok(Val::Char(s[i])),
+        i => Err(ErrType::index(i as i64, s.len())),
+    }
+}
+
+/// `(substring s start [end])` takes the chars of `s` from `start` up to
+/// `end`, or to the end of `s`.
+pub fn substring(args: Args) -> Res {
+    let s = chars(&args[0])?;
+    let start = position(&args[1], s.len())?;
+    let end = match args.get(2) {
+        Some(end) => position(end, s.len())?,
+        None => s.len(),
+    };
+    if start > end {
+        return Err(ErrType::index(start as i64, end));
+    }
+    Ok(Val::string(&s[start..end].iter().collect::<String>()))
+}
+
+pub fn string_append(args: Args) -> Res {
+    let mut out = String::new();
+    for a in &args {
+        out.push_str(&a.unwrap_string()?);
+    }
+    Ok(Val::string(&out))
+}
+
+/// Whether each of `args` relates to the next as `ordered` says.
+fn compare(args: Args, ordered: fn(&str, &str) -> bool) -> Res {
+    let strings = args.iter().map(Val::unwrap_string).collect::<Result<Vec<_>, _>>()?;
+    Ok(Val::Bool(strings.windows(2).all(|w| ordered(&w[0], &w[1]))))
+}
+
+pub fn string_eq(args: Args) -> Res {
+    compare(args, |a, b| a == b)
+}
+
+pub fn string_lt(args: Args) -> Res {
+    compare(args, |a, b| a < b)
+}
+
+pub fn string_upcase(args: Args) -> Res {
+    Ok(Val::string(&args[0].unwrap_string()?.to_uppercase()))
+}
+
+pub fn string_downcase(args: Args) -> Res {
+    Ok(Val::string(&args[0].unwrap_string()?.to_lowercase()))
+}
+
+pub const NAMES: &[&str] = &[
+    "string?", "string-length", "string-ref", "substring", "string-append", "string=?", "string<?",
+    "string-upcase", "string-downcase",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("string?", Arity::SomeArgs(1), string_p);
+    e.register_func("string-length", Arity::SomeArgs(1), string_length);
+    e.register_func("string-ref", Arity::SomeArgs(2), string_ref);
+    e.register_func("substring", Arity::Range(2, 3), substring);
+    e.register_func("string-append", Arity::VarArgs, string_append);
+    e.register_func("string=?", Arity::AtLeast(1), string_eq);
+    e.register_func("string<?", Arity::AtLeast(1), string_lt);
+    e.register_func("string-upcase", Arity::SomeArgs(1), string_upcase);
+    e.register_func("string-downcase", Arity::SomeArgs(1), string_downcase);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ErrType;
+    use crate::types::Val;
+    use crate::stdlib::string::*;
+
+    fn s(text: &str) -> Val {
+        Val::string(text)
+    }
+
+    #[test]
+    fn test_string_p() -> Result<(), ErrType> {
+        assert_eq!(string_p(vec![s("ab")])?, Val::Bool(true));
+        assert_eq!(string_p(vec![s("")])?, Val::Bool(true));
+        assert_eq!(string_p(vec![Val::List(vec![Val::Int(1)].into())])?, Val::Bool(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_length_and_ref() -> Result<(), ErrType> {
+        assert_eq!(string_length(vec![s("héllo")])?, Val::Int(5));
+        assert_eq!(string_ref(vec![s("héllo"), Val::Int(1)])?, Val::Char('é'));
+        assert_eq!(string_ref(vec![s("ab"), Val::Int(2)]), Err(ErrType::index(2, 2)));
+        assert!(string_length(vec![Val::Int(1)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_substring() -> Result<(), ErrType> {
+        assert_eq!(substring(vec![s("hello"), Val::Int(1), Val::Int(3)])?, s("el"));
+        assert_eq!(substring(vec![s("hello"), Val::Int(3)])?, s("lo"));
+        assert_eq!(substring(vec![s("hello"), Val::Int(5)])?, s(""));
+        assert_eq!(substring(vec![s("hello"), Val::Int(6)]), Err(ErrType::index(6, 5)));
+        assert!(substring(vec![s("hello"), Val::Int(3), Val::Int(1)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_and_compare() -> Result<(), ErrType> {
+        assert_eq!(string_append(vec![s("ab"), s(""), s("c")])?, s("abc"));
+        assert_eq!(string_append(vec![])?, s(""));
+        assert_eq!(string_eq(vec![s("a"), s("a"), s("a")])?, Val::Bool(true));
+        assert_eq!(string_eq(vec![s("a"), s("b")])?, Val::Bool(false));
+        assert_eq!(string_lt(vec![s("a"), s("ab"), s("b")])?, Val::Bool(true));
+        assert_eq!(string_lt(vec![s("b"), s("a")])?, Val::Bool(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_case() -> Result<(), ErrType> {
+        assert_eq!(string_upcase(vec![s("Straße")])?, s("STRASSE"));
+        assert_eq!(string_downcase(vec![s("ABC")])?, s("abc"));
+        Ok(())
+    }
+}
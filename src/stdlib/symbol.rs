@@ -0,0 +1,65 @@
+use crate::env::{Arity, Env};
+use crate::symbol::Symbol;
+use crate::types::{Args, Res, Val};
+
+pub fn symbol_to_string(args: Args) -> Res {
+    let s = args[0].unwrap_symbol()?;
+    Ok(Val::string(s.as_str()))
+}
+
+pub fn string_to_symbol(args: Args) -> Res {
+    let name = args[0].unwrap_string()?;
+    Ok(Val::Symbol(Symbol::intern(&name)))
+}
+
+pub fn gensym(_: Args) -> Res {
+    Ok(Val::Symbol(Symbol::gensym()))
+}
+
+pub const NAMES: &[&str] = &[
+    "symbol->string", "string->symbol", "gensym",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("symbol->string", Arity::SomeArgs(1), symbol_to_string);
+    e.register_func("string->symbol", Arity::SomeArgs(1), string_to_symbol);
+    e.register_func("gensym", Arity::NoArgs, gensym);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ErrType;
+    use crate::symbol::Symbol;
+    use crate::types::Val;
+    use crate::stdlib::symbol::*;
+
+    #[test]
+    fn test_symbol_to_string() -> Result<(), ErrType> {
+        let res = symbol_to_string(vec![Val::Symbol(Symbol::intern("ab"))])?;
+        assert_eq!(res, Val::List(vec![Val::Char('a'), Val::Char('b')].into()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_string_to_symbol() -> Result<(), ErrType> {
+        let s = Val::List(vec![Val::Char('a'), Val::Char('b')].into());
+        let res = string_to_symbol(vec![s])?;
+        assert_eq!(res, Val::Symbol(Symbol::intern("ab")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_string_to_symbol_type_error() {
+        let s = Val::List(vec![Val::Int(1)].into());
+        let expected = ErrType::type_error("char", "int");
+        assert_eq!(string_to_symbol(vec![s]), Err(expected));
+    }
+
+    #[test]
+    fn test_gensym() -> Result<(), ErrType> {
+        let a = gensym(vec![])?;
+        let b = gensym(vec![])?;
+        assert_ne!(a, b);
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+use crate::env::{Arity, Env};
+use crate::symbol::Symbol;
+use crate::types::{Args, Res, Val};
+
+/// `(type-of x)` names the type of `x` as a symbol, e.g. `'int`; a record's
+/// type is named by its record type. Builtins, procedures defined in Lisp
+/// and record procedures are all `'procedure`.
+pub fn type_of(args: Args) -> Res {
+    Ok(Val::Symbol(Symbol::intern(args[0].val_type())))
+}
+
+pub fn int_p(args: Args) -> Res {
+    Ok(Val::Bool(matches!(args[0], Val::Int(_))))
+}
+
+pub fn char_p(args: Args) -> Res {
+    Ok(Val::Bool(matches!(args[0], Val::Char(_))))
+}
+
+pub fn bool_p(args: Args) -> Res {
+    Ok(Val::Bool(matches!(args[0], Val::Bool(_))))
+}
+
+pub fn not(args: Args) -> Res {
+    Ok(Val::Bool(!args[0].is_truthy()))
+}
+
+pub fn list_p(args: Args) -> Res {
+    Ok(Val::Bool(matches!(args[0], Val::List(_))))
+}
+
+pub fn symbol_p(args: Args) -> Res {
+    Ok(Val::Bool(matches!(args[0], Val::Symbol(_))))
+}
+
+pub fn vector_p(args: Args) -> Res {
+    Ok(Val::Bool(matches!(args[0], Val::Vector(_))))
+}
+
+pub fn map_p(args: Args) -> Res {
+    Ok(Val::Bool(matches!(args[0], Val::Map(_))))
+}
+
+pub fn pvector_p(args: Args) -> Res {
+    Ok(Val::Bool(matches!(args[0], Val::PVector(_))))
+}
+
+pub fn set_p(args: Args) -> Res {
+    Ok(Val::Bool(matches!(args[0], Val::Set(_))))
+}
+
+/// `(procedure? x)` holds for procedures, including continuations.
+pub fn procedure_p(args: Args) -> Res {
+    Ok(Val::Bool(matches!(args[0], Val::Procedure(_) | Val::Continuation(_))))
+}
+
+pub const NAMES: &[&str] = &[
+    "type-of", "int?", "char?", "bool?", "not", "list?", "symbol?", "vector?", "map?", "pvector?",
+    "set?", "procedure?",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("type-of", Arity::SomeArgs(1), type_of);
+    e.register_func("int?", Arity::SomeArgs(1), int_p);
+    e.register_func("char?", Arity::SomeArgs(1), char_p);
+    e.register_func("bool?", Arity::SomeArgs(1), bool_p);
+    e.register_func("not", Arity::SomeArgs(1), not);
+    e.register_func("list?", Arity::SomeArgs(1), list_p);
+    e.register_func("symbol?", Arity::SomeArgs(1), symbol_p);
+    e.register_func("vector?", Arity::SomeArgs(1), vector_p);
+    e.register_func("map?", Arity::SomeArgs(1), map_p);
+    e.register_func("pvector?", Arity::SomeArgs(1), pvector_p);
+    e.register_func("set?", Arity::SomeArgs(1), set_p);
+    e.register_func("procedure?", Arity::SomeArgs(1), procedure_p);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ErrType;
+    use crate::symbol::Symbol;
+    use crate::types::Val;
+    use crate::stdlib::types::*;
+
+    #[test]
+    fn test_type_of() -> Result<(), ErrType> {
+        assert_eq!(type_of(vec![Val::Int(1)])?, Val::Symbol(Symbol::intern("int")));
+        assert_eq!(type_of(vec![Val::Nil])?, Val::Symbol(Symbol::intern("nil")));
+        assert_eq!(type_of(vec![Val::vector(vec![])])?, Val::Symbol(Symbol::intern("vector")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_predicates() -> Result<(), ErrType> {
+        assert_eq!(int_p(vec![Val::Int(1)])?, Val::Bool(true));
+        assert_eq!(int_p(vec![Val::Char('1')])?, Val::Bool(false));
+        assert_eq!(list_p(vec![Val::string("")])?, Val::Bool(true));
+        assert_eq!(list_p(vec![Val::Nil])?, Val::Bool(false));
+        assert_eq!(not(vec![Val::Bool(false)])?, Val::Bool(true));
+        assert_eq!(not(vec![Val::Nil])?, Val::Bool(false));
+        assert_eq!(symbol_p(vec![Val::Symbol(Symbol::intern("a"))])?, Val::Bool(true));
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use std::convert::TryFrom;
+
+use crate::env::{Arity, Env};
+use crate::errors::ErrType;
+use crate::types::{Args, Res, Val};
+
+fn index(i: &Val, len: usize) -> Result<usize, ErrType> {
+    let i = *i.unwrap_int()?;
+    usize::try_from(i).ok().filter(|&u| u < len).ok_or_else(|| ErrType::index(i, len))
+}
+
+pub fn vector(args: Args) -> Res {
+    Ok(Val::vector(args))
+}
+
+pub fn vector_ref(args: Args) -> Res {
+    let v = args[0].unwrap_vector()?.borrow();
+    let i = index(&args[1], v.len())?;
+    Ok(v[i].clone())
+}
+
+pub fn vector_set(args: Args) -> Res {
+    let mut v = args[0].unwrap_vector()?.borrow_mut();
+    let i = index(&args[1], v.len())?;
+    v[i] = args[2].clone();
+    Ok(Val::Nil)
+}
+
+pub fn vector_length(args: Args) -> Res {
+    let v = args[0].unwrap_vector()?.borrow();
+    Ok(Val::Int(v.len() as i64))
+}
+
+pub const NAMES: &[&str] = &[
+    "vector", "vector-ref", "vector-set!", "vector-length",
+];
+
+pub fn register(e: &Env) {
+    e.register_func("vector", Arity::VarArgs, vector);
+    e.register_func("vector-ref", Arity::SomeArgs(2), vector_ref);
+    e.register_func("vector-set!", Arity::SomeArgs(3), vector_set);
+    e.register_func("vector-length", Arity::SomeArgs(1), vector_length);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ErrType;
+    use crate::types::Val;
+    use crate::stdlib::vector::*;
+
+    #[test]
+    fn test_vector_ref() -> Result<(), ErrType> {
+        let v = vector(vec![Val::Int(1), Val::Int(2)])?;
+        assert_eq!(vector_ref(vec![v.clone(), Val::Int(1)])?, Val::Int(2));
+        assert_eq!(vector_ref(vec![v.clone(), Val::Int(2)]), Err(ErrType::index(2, 2)));
+        assert_eq!(vector_ref(vec![v, Val::Int(-1)]), Err(ErrType::index(-1, 2)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_set() -> Result<(), ErrType> {
+        let v = vector(vec![Val::Int(1), Val::Int(2)])?;
+        let alias = v.clone();
+        assert_eq!(vector_set(vec![v, Val::Int(0), Val::Char('a')])?, Val::Nil);
+        assert_eq!(*alias.unwrap_vector()?.borrow(), vec![Val::Char('a'), Val::Int(2)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_length() -> Result<(), ErrType> {
+        assert_eq!(vector_length(vec![Val::vector(vec![Val::Int(1)])])?, Val::Int(1));
+        assert_eq!(vector_length(vec![Val::Int(1)]), Err(ErrType::type_error("vector", "int")));
+        Ok(())
+    }
+}
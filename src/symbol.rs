@@ -0,0 +1,106 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// An interned symbol. Symbols are interned per thread, so a `Symbol` is
+/// neither `Send` nor `Sync`: its id only names something on the thread that
+/// created it.
+///
+/// ```compile_fail
+/// let s = lisp::symbol::Symbol::intern("a");
+/// std::thread::spawn(move || s.as_str());
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Symbol(u32, PhantomData<*const ()>);
+
+#[derive(Default)]
+struct Interner {
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, Symbol>,
+    /// The total length of `names`, in chars.
+    len: u64,
+}
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(s) = self.ids.get(name) {
+            return *s;
+        }
+        let s = self.push(name);
+        self.ids.insert(self.names[s.0 as usize], s);
+        s
+    }
+    fn push(&mut self, name: &str) -> Symbol {
+        let s = Symbol(self.names.len() as u32, PhantomData);
+        self.names.push(Box::leak(name.into()));
+        self.len += name.chars().count() as u64;
+        s
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|i| i.borrow_mut().intern(name))
+    }
+    /// Creates a symbol that is never returned by `intern`, even for its own name.
+    pub fn gensym() -> Symbol {
+        INTERNER.with(|i| {
+            let mut i = i.borrow_mut();
+            let name = format!("g{}", i.names.len());
+            i.push(&name)
+        })
+    }
+    pub fn as_str(self) -> &'static str {
+        INTERNER.with(|i| i.borrow().names[self.0 as usize])
+    }
+    /// The total length, in chars, of the names of the symbols created on
+    /// this thread so far. Names are never freed, so this only grows.
+    pub fn interned_len() -> u64 {
+        INTERNER.with(|i| i.borrow().len)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        assert_eq!(Symbol::intern("a"), Symbol::intern("a"));
+        assert_ne!(Symbol::intern("a"), Symbol::intern("b"));
+        assert_eq!(Symbol::intern("abc").as_str(), "abc");
+    }
+
+    #[test]
+    fn test_gensym() {
+        let g = Symbol::gensym();
+        assert_ne!(g, Symbol::gensym());
+        assert_ne!(g, Symbol::intern(g.as_str()));
+    }
+
+    #[test]
+    fn test_interned_len() {
+        let before = Symbol::interned_len();
+        Symbol::intern("héllo, interner");
+        assert_eq!(Symbol::interned_len(), before + 15);
+        Symbol::intern("héllo, interner");
+        assert_eq!(Symbol::interned_len(), before + 15);
+    }
+}
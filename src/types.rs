@@ -1,6 +1,18 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::ops::Deref;
+use std::rc::Rc;
 
+use super::env::Procedure;
 use super::errors::ErrType;
+use super::eval::Continuation;
+use super::gc::{self, Tracked};
+use super::persistent::{PMap, PSet, PVector};
+use super::symbol::Symbol;
 
 pub type Res = Result<Val, ErrType>;
 
@@ -9,88 +21,437 @@ pub type Args = Vec<Val>;
 pub type F = fn(Args) -> Res;
 
 
+/// A Lisp value. Values are reference counted, so a value that comes to
+/// contain itself isn't freed when the last reference to it goes: a vector
+/// or record put inside itself, or a procedure stored in the environment it
+/// holds on to. `gc::collect` frees such cycles; see there for those it
+/// can't.
 #[derive(Clone, Debug)]
 pub enum Val {
+    /// The value of forms evaluated only for their effect, such as `define`.
+    /// Unlike `()`, it isn't a list.
+    Nil,
     Int(i64),
     Char(char),
     Bool(bool),
-    List(Vec<Val>),
-    Symbol(String),
+    List(List),
+    Symbol(Symbol),
+    Error(Rc<ErrType>),
+    Procedure(Rc<Procedure>),
+    Continuation(Rc<Continuation>),
+    /// A mutable vector. A vector is equal only to itself, so mutating it
+    /// can't change its hash while it's a map key or set member.
+    Vector(Rc<RefCell<Vec<Val>>>),
+    Map(PMap<Val, Val>),
+    PVector(PVector<Val>),
+    Set(PSet<Val>),
+    /// An instance of a record type. Like a vector, a record is mutable, and
+    /// equal only to itself.
+    Record(Rc<Record>),
 }
 
+/// A record type created by `define-record-type`.
+#[derive(Debug)]
+pub struct RecordType {
+    pub name: Symbol,
+    pub fields: Vec<Symbol>,
+}
+
+#[derive(Debug)]
+pub struct Record {
+    pub rtype: Rc<RecordType>,
+    pub fields: RefCell<Vec<Val>>,
+}
+
+/// An immutable list whose storage is shared between clones, so taking
+/// the tail of a list is O(1) rather than copying the remaining elements.
+#[derive(Clone)]
+pub struct List {
+    items: Rc<[Val]>,
+    start: usize,
+}
+
+impl List {
+    pub fn new() -> Self {
+        Self::from(Vec::new())
+    }
+    pub fn rest(&self) -> List {
+        List {
+            items: Rc::clone(&self.items),
+            start: (self.start + 1).min(self.items.len()),
+        }
+    }
+    /// Every item this list's storage holds, including any before its
+    /// start, if no other list shares the storage, and none otherwise.
+    pub fn unshared(&self) -> &[Val] {
+        if Rc::strong_count(&self.items) == 1 {
+            &self.items
+        } else {
+            &[]
+        }
+    }
+}
+
+impl Default for List {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Vec<Val>> for List {
+    fn from(v: Vec<Val>) -> Self {
+        List { items: v.into(), start: 0 }
+    }
+}
+
+impl Deref for List {
+    type Target = [Val];
+
+    fn deref(&self) -> &[Val] {
+        &self.items[self.start..]
+    }
+}
+
+impl PartialEq for List {
+    fn eq(&self, other: &List) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for List {}
+
+impl Hash for List {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl fmt::Debug for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Values can nest arbitrarily deep, such as a list built up by a long loop,
+// so comparing, hashing, displaying and dropping them keep a stack of their
+// own rather than recursing, which could overflow the host's.
+
 impl PartialEq for Val {
     fn eq(&self, other: &Val) -> bool {
-        match self {
-            Val::Int(a) => {
-                match other {
-                    Val::Int(b) => a == b,
-                    _ => false
-                }
-            },
-            Val::Char(a) => {
-                match other {
-                    Val::Char(b) => a == b,
-                    _ => false
-                }
-            },
-            Val::Bool(a) => {
-                match other {
-                    Val::Bool(b) => a == b,
-                    _ => false
-                }
-            },
-            Val::List(a) => {
-                match other {
-                    Val::List(b) => a == b,
-                    _ => false
+        let mut pairs = vec![(self, other)];
+        while let Some(pair) = pairs.pop() {
+            let same = match pair {
+                (Val::Nil, Val::Nil) => true,
+                (Val::Int(a), Val::Int(b)) => a == b,
+                (Val::Char(a), Val::Char(b)) => a == b,
+                (Val::Bool(a), Val::Bool(b)) => a == b,
+                (Val::Symbol(a), Val::Symbol(b)) => a == b,
+                (Val::Error(a), Val::Error(b)) => a == b,
+                (Val::Procedure(a), Val::Procedure(b)) => Rc::ptr_eq(a, b),
+                (Val::Continuation(a), Val::Continuation(b)) => Rc::ptr_eq(a, b),
+                (Val::Vector(a), Val::Vector(b)) => Rc::ptr_eq(a, b),
+                (Val::Record(a), Val::Record(b)) => Rc::ptr_eq(a, b),
+                (Val::List(a), Val::List(b)) => {
+                    pairs.extend(a.iter().zip(b.iter()));
+                    a.len() == b.len()
                 }
-            },
-            Val::Symbol(a) => {
-                match other {
-                    Val::Symbol(b) => a == b,
-                    _ => false
+                (Val::PVector(a), Val::PVector(b)) => {
+                    pairs.extend(a.iter().zip(b.iter()));
+                    a.len() == b.len()
                 }
+                // Looking up keys and members compares them by recursing,
+                // but only as deep as maps and sets nest inside them.
+                (Val::Map(a), Val::Map(b)) => a.len() == b.len() && a.iter().all(|(k, v)| match b.get(k) {
+                    Some(w) => {
+                        pairs.push((v, w));
+                        true
+                    }
+                    None => false,
+                }),
+                (Val::Set(a), Val::Set(b)) => a == b,
+                _ => false,
+            };
+            if !same {
+                return false;
             }
         }
+        true
     }
 }
 
 impl Eq for Val {}
 
-impl fmt::Display for Val {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+/// A step in hashing a value bottom up: hash a value, or combine the hashes
+/// of the last `n` values hashed into that of the collection holding them.
+enum HashStep<'a> {
+    Visit(&'a Val),
+    Combine { of: &'a Val, n: usize },
+}
+
+/// The hash of `v`. Lists and pvectors hash their elements' hashes in order,
+/// and maps and sets sum those of their entries, whose order isn't defined.
+fn hash_code(v: &Val) -> u64 {
+    let hash_one = |x: &dyn Fn(&mut DefaultHasher)| {
+        let mut s = DefaultHasher::new();
+        x(&mut s);
+        s.finish()
+    };
+    let mut steps = vec![HashStep::Visit(v)];
+    let mut hashes: Vec<u64> = Vec::new();
+    while let Some(step) = steps.pop() {
+        match step {
+            HashStep::Visit(v) => {
+                let children: Vec<&Val> = match v {
+                    Val::List(a) => a.iter().collect(),
+                    Val::PVector(a) => a.iter().collect(),
+                    Val::Map(a) => a.iter().flat_map(|(k, v)| vec![k, v]).collect(),
+                    Val::Set(a) => a.iter().collect(),
+                    _ => {
+                        hashes.push(hash_one(&|s| hash_leaf(v, s)));
+                        continue;
+                    }
+                };
+                steps.push(HashStep::Combine { of: v, n: children.len() });
+                steps.extend(children.into_iter().rev().map(HashStep::Visit));
+            }
+            HashStep::Combine { of, n } => {
+                let children = hashes.split_off(hashes.len() - n);
+                let combined = match of {
+                    Val::Map(_) => children.chunks(2)
+                        .map(|kv| hash_one(&|s| kv.hash(s)))
+                        .fold(0u64, u64::wrapping_add),
+                    Val::Set(_) => children.iter().fold(0u64, |h, c| h.wrapping_add(*c)),
+                    _ => hash_one(&|s| children.hash(s)),
+                };
+                hashes.push(hash_one(&|s| {
+                    mem::discriminant(of).hash(s);
+                    combined.hash(s);
+                }));
+            }
+        }
+    }
+    hashes[0]
+}
+
+/// Hashes a value that holds no others, or that's equal only to itself.
+fn hash_leaf<H: Hasher>(v: &Val, state: &mut H) {
+    mem::discriminant(v).hash(state);
+    match v {
+        Val::Int(a) => a.hash(state),
+        Val::Char(a) => a.hash(state),
+        Val::Bool(a) => a.hash(state),
+        Val::Symbol(a) => a.hash(state),
+        Val::Procedure(a) => Rc::as_ptr(a).hash(state),
+        Val::Continuation(a) => Rc::as_ptr(a).hash(state),
+        Val::Vector(a) => Rc::as_ptr(a).hash(state),
+        Val::Record(a) => Rc::as_ptr(a).hash(state),
+        _ => (),
+    }
+}
+
+impl Hash for Val {
+    fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
-            Val::Int(a) => write!(f, "{}", a),
-            Val::Char(a) => write!(f, "{}", a),
-            Val::Bool(a) => write!(f, "{}", a),
-            Val::Symbol(a) => write!(f, "'{}", a),
+            Val::List(_) | Val::PVector(_) | Val::Map(_) | Val::Set(_) => hash_code(self).hash(state),
+            _ => hash_leaf(self, state),
+        }
+    }
+}
+
+impl Drop for Val {
+    /// Moves the elements of collections nothing else shares onto a stack
+    /// before the collections are freed, so that freeing them doesn't
+    /// recurse into their elements.
+    fn drop(&mut self) {
+        let mut orphans = Vec::new();
+        take_unshared(self, &mut orphans);
+        while let Some(mut v) = orphans.pop() {
+            take_unshared(&mut v, &mut orphans);
+        }
+    }
+}
+
+/// Moves the elements out of `v` to `out` if no other value shares them.
+fn take_unshared(v: &mut Val, out: &mut Vec<Val>) {
+    match v {
+        Val::List(l) => {
+            if let Some(items) = Rc::get_mut(&mut l.items) {
+                out.extend(items.iter_mut().map(|x| mem::replace(x, Val::Nil)));
+            }
+        }
+        // The collector's weak references keep `Rc::get_mut` from working
+        // on vectors and records.
+        Val::Vector(a) if Rc::strong_count(a) == 1 => {
+            if let Ok(mut items) = a.try_borrow_mut() {
+                out.append(&mut items);
+            }
+        }
+        Val::Record(r) if Rc::strong_count(r) == 1 => {
+            if let Ok(mut fields) = r.fields.try_borrow_mut() {
+                out.append(&mut fields);
+            }
+        }
+        Val::PVector(a) => a.take_unshared(out),
+        Val::Set(a) => a.take_unshared(out),
+        Val::Map(a) => {
+            let mut entries = Vec::new();
+            a.take_unshared(&mut entries);
+            out.extend(entries.into_iter().flat_map(|(k, v)| vec![k, v]));
+        }
+        _ => (),
+    }
+}
+
+thread_local! {
+    /// The vectors being displayed, so that a vector inside itself is shown
+    /// as `#<vector ...>` rather than without end.
+    static DISPLAYING: RefCell<HashSet<*const RefCell<Vec<Val>>>> = RefCell::new(HashSet::new());
+}
+
+/// What's left to write of a value being displayed, last first.
+enum Piece {
+    Val(Val),
+    /// A map entry.
+    Entry(Val, Val),
+    Text(&'static str),
+    /// The end of a vector's elements.
+    Leave(*const RefCell<Vec<Val>>),
+}
+
+/// Pushes `items` onto `pieces` between `open` and `close`, separated by
+/// commas, so they're written in order.
+fn push_items(pieces: &mut Vec<Piece>, open: &'static str, items: Vec<Piece>, close: &'static str) {
+    pieces.push(Piece::Text(close));
+    for (i, item) in items.into_iter().enumerate().rev() {
+        pieces.push(item);
+        if i > 0 {
+            pieces.push(Piece::Text(", "));
+        }
+    }
+    pieces.push(Piece::Text(open));
+}
+
+fn fmt_pieces(pieces: &mut Vec<Piece>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    while let Some(piece) = pieces.pop() {
+        let v = match piece {
+            Piece::Val(v) => v,
+            Piece::Entry(k, v) => {
+                pieces.extend([Piece::Val(v), Piece::Text(" "), Piece::Val(k)]);
+                continue;
+            }
+            Piece::Text(s) => {
+                f.write_str(s)?;
+                continue;
+            }
+            Piece::Leave(ptr) => {
+                DISPLAYING.with(|d| d.borrow_mut().remove(&ptr));
+                continue;
+            }
+        };
+        match &v {
+            Val::Nil => write!(f, "#<nil>")?,
+            Val::Int(a) => write!(f, "{}", a)?,
+            Val::Char(a) => write!(f, "{}", a)?,
+            Val::Bool(a) => write!(f, "{}", a)?,
+            Val::Symbol(a) => write!(f, "'{}", a)?,
+            Val::Error(a) => write!(f, "#<error {}>", a.root())?,
+            Val::Procedure(a) => write!(f, "#<procedure {}>", a.name)?,
+            Val::Continuation(_) => write!(f, "#<continuation>")?,
+            Val::Record(a) => write!(f, "#<record {}>", a.rtype.name)?,
             Val::List(a) => {
-                let l = a.len() - 1;
-                write!(f, "(")?;
-                for v in a[0..l].iter() {
-                    write!(f, "{}, ", v)?;
+                let items = a.iter().cloned().map(Piece::Val).collect();
+                push_items(pieces, "(", items, ")");
+            }
+            Val::Vector(a) => {
+                let ptr = Rc::as_ptr(a);
+                if !DISPLAYING.with(|d| d.borrow_mut().insert(ptr)) {
+                    write!(f, "#<vector ...>")?;
+                    continue;
                 }
-                write!(f, "{})", a[l])?;
-                Ok(())
+                pieces.push(Piece::Leave(ptr));
+                let items = a.borrow().iter().cloned().map(Piece::Val).collect();
+                push_items(pieces, "#(", items, ")");
+            }
+            Val::Map(a) => {
+                let items = a.iter().map(|(k, v)| Piece::Entry(k.clone(), v.clone())).collect();
+                push_items(pieces, "{", items, "}");
+            }
+            Val::PVector(a) => {
+                let items = a.iter().cloned().map(Piece::Val).collect();
+                push_items(pieces, "[", items, "]");
+            }
+            Val::Set(a) => {
+                let items = a.iter().cloned().map(Piece::Val).collect();
+                push_items(pieces, "#{", items, "}");
             }
         }
     }
+    Ok(())
+}
+
+impl fmt::Display for Val {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pieces = vec![Piece::Val(self.clone())];
+        let res = fmt_pieces(&mut pieces, f);
+        // Forget the vectors an error left half displayed.
+        for piece in pieces {
+            if let Piece::Leave(ptr) = piece {
+                DISPLAYING.with(|d| d.borrow_mut().remove(&ptr));
+            }
+        }
+        res
+    }
 }
 
 impl Val {
+    const NIL_TYPE: &'static str = "nil";
     const INT_TYPE: &'static str = "int";
     const CHAR_TYPE: &'static str = "char";
     const BOOL_TYPE: &'static str = "bool";
     const LIST_TYPE: &'static str = "list";
+    const PAIR_TYPE: &'static str = "non-empty list";
+    const EMPTY_LIST_TYPE: &'static str = "empty list";
     const SYMBOL_TYPE: &'static str = "symbol";
+    const ERROR_TYPE: &'static str = "error";
+    const PROCEDURE_TYPE: &'static str = "procedure";
+    const CONTINUATION_TYPE: &'static str = "continuation";
+    const VECTOR_TYPE: &'static str = "vector";
+    const MAP_TYPE: &'static str = "map";
+    const PVECTOR_TYPE: &'static str = "pvector";
+    const SET_TYPE: &'static str = "set";
 
-    fn val_type(&self) -> &str {
+    /// Makes a string, represented as a list of chars.
+    pub fn string(s: &str) -> Val {
+        Val::List(s.chars().map(Val::Char).collect::<Vec<_>>().into())
+    }
+    pub fn vector(v: Vec<Val>) -> Val {
+        let v = Rc::new(RefCell::new(v));
+        gc::track(Tracked::Vector(Rc::downgrade(&v)));
+        Val::Vector(v)
+    }
+    /// Everything except `#f` counts as true in a conditional, including
+    /// `0`, `()` and nil.
+    pub fn is_truthy(&self) -> bool {
+        *self != Val::Bool(false)
+    }
+
+    /// The name of this value's type, as used in type errors and by `type-of`.
+    pub fn val_type(&self) -> &str {
         match self {
+            Val::Nil => Val::NIL_TYPE,
             Val::Int(_) => Val::INT_TYPE,
             Val::Char(_) => Val::CHAR_TYPE,
             Val::Bool(_) => Val::BOOL_TYPE,
             Val::List(_) => Val::LIST_TYPE,
             Val::Symbol(_) => Val::SYMBOL_TYPE,
+            Val::Error(_) => Val::ERROR_TYPE,
+            Val::Procedure(_) => Val::PROCEDURE_TYPE,
+            Val::Continuation(_) => Val::CONTINUATION_TYPE,
+            Val::Vector(_) => Val::VECTOR_TYPE,
+            Val::Map(_) => Val::MAP_TYPE,
+            Val::PVector(_) => Val::PVECTOR_TYPE,
+            Val::Set(_) => Val::SET_TYPE,
+            Val::Record(r) => r.rtype.name.as_str(),
         }
     }
     pub fn unwrap_int(&self) -> Result<&i64, ErrType> {
@@ -111,18 +472,66 @@ impl Val {
             _ => Err(ErrType::type_error(Val::BOOL_TYPE, self.val_type())),
         }
     }
-    pub fn unwrap_list(&self) -> Result<&Vec<Val>, ErrType> {
+    pub fn unwrap_list(&self) -> Result<&List, ErrType> {
         match self {
             Val::List(a) => Ok(a),
             _ => Err(ErrType::type_error(Val::LIST_TYPE, self.val_type())),
         }
     }
-    pub fn unwrap_symbol(&self) -> Result<&str, ErrType> {
+    /// Unwraps a list with at least one element.
+    pub fn unwrap_pair(&self) -> Result<&List, ErrType> {
+        match self.unwrap_list()? {
+            l if l.is_empty() => Err(ErrType::type_error(Val::PAIR_TYPE, Val::EMPTY_LIST_TYPE)),
+            l => Ok(l),
+        }
+    }
+    pub fn unwrap_symbol(&self) -> Result<&Symbol, ErrType> {
         match self {
-            Val::Symbol(a) => Ok(&a),
+            Val::Symbol(a) => Ok(a),
             _ => Err(ErrType::type_error(Val::SYMBOL_TYPE, self.val_type())),
          }
      }
+    pub fn unwrap_string(&self) -> Result<String, ErrType> {
+        let l = self.unwrap_list()?;
+        l.iter().map(|c| c.unwrap_char().copied()).collect()
+    }
+    pub fn unwrap_vector(&self) -> Result<&RefCell<Vec<Val>>, ErrType> {
+        match self {
+            Val::Vector(a) => Ok(a),
+            _ => Err(ErrType::type_error(Val::VECTOR_TYPE, self.val_type())),
+        }
+    }
+    pub fn unwrap_map(&self) -> Result<&PMap<Val, Val>, ErrType> {
+        match self {
+            Val::Map(a) => Ok(a),
+            _ => Err(ErrType::type_error(Val::MAP_TYPE, self.val_type())),
+        }
+    }
+    pub fn unwrap_pvector(&self) -> Result<&PVector<Val>, ErrType> {
+        match self {
+            Val::PVector(a) => Ok(a),
+            _ => Err(ErrType::type_error(Val::PVECTOR_TYPE, self.val_type())),
+        }
+    }
+    pub fn unwrap_set(&self) -> Result<&PSet<Val>, ErrType> {
+        match self {
+            Val::Set(a) => Ok(a),
+            _ => Err(ErrType::type_error(Val::SET_TYPE, self.val_type())),
+        }
+    }
+    /// Unwraps a record of type `rtype`.
+    pub fn unwrap_record(&self, rtype: &Rc<RecordType>) -> Result<&Record, ErrType> {
+        match self {
+            Val::Record(r) if Rc::ptr_eq(&r.rtype, rtype) => Ok(r),
+            _ => Err(ErrType::type_error(rtype.name.as_str(), self.val_type())),
+        }
+    }
+    pub fn unwrap_error(&self) -> Result<&ErrType, ErrType> {
+        match self {
+            Val::Error(a) => Ok(a),
+            _ => Err(ErrType::type_error(Val::ERROR_TYPE, self.val_type())),
+        }
+    }
 }
 
 
@@ -132,13 +541,93 @@ mod test {
 
     #[test]
     fn test_display_symbol() {
-        let s = Val::Symbol(String::from("a"));
+        let s = Val::Symbol(Symbol::intern("a"));
         assert_eq!(String::from("'a"), s.to_string());
     }
 
     #[test]
     fn test_display_list() {
-        let l = Val::List(vec![Val::Int(1), Val::Int(2), Val::Int(3)]);
+        let l = Val::List(vec![Val::Int(1), Val::Int(2), Val::Int(3)].into());
         assert_eq!(String::from("(1, 2, 3)"), l.to_string());
     }
+
+    fn hash(v: &Val) -> u64 {
+        let mut s = DefaultHasher::new();
+        v.hash(&mut s);
+        s.finish()
+    }
+
+    #[test]
+    fn test_hash_consistent_with_eq() {
+        let a = Val::List(vec![Val::Int(1), Val::Int(2)].into());
+        let b = Val::List(vec![Val::Int(0), Val::Int(1), Val::Int(2)].into());
+        let b = Val::List(b.unwrap_list().unwrap().rest());
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+        let m1: PMap<Val, Val> = vec![(Val::Int(1), a.clone()), (Val::Int(2), b.clone())].into_iter().collect();
+        let m2: PMap<Val, Val> = vec![(Val::Int(2), b), (Val::Int(1), a)].into_iter().collect();
+        assert_eq!(hash(&Val::Map(m1)), hash(&Val::Map(m2)));
+        assert_ne!(hash(&Val::Int(1)), hash(&Val::vector(vec![Val::Int(1)])));
+        let s1: PSet<Val> = (0..50).map(Val::Int).collect();
+        let s2: PSet<Val> = (0..50).rev().map(Val::Int).collect();
+        assert_eq!(hash(&Val::Set(s1)), hash(&Val::Set(s2)));
+    }
+
+    #[test]
+    fn test_display_vector() {
+        let v = Val::vector(vec![Val::Int(1), Val::Char('a')]);
+        assert_eq!(v.to_string(), "#(1, a)");
+        let inner = v.unwrap_vector().unwrap();
+        inner.borrow_mut().push(Val::vector(vec![v.clone()]));
+        assert_eq!(v.to_string(), "#(1, a, #(#<vector ...>))");
+        inner.borrow_mut().clear();
+    }
+
+    #[test]
+    fn test_vector_identity() {
+        let v = Val::vector(vec![Val::Int(1)]);
+        assert_eq!(v, v.clone());
+        assert_ne!(v, Val::vector(vec![Val::Int(1)]));
+        let before = hash(&v);
+        v.unwrap_vector().unwrap().borrow_mut().push(v.clone());
+        assert_eq!(hash(&v), before);
+        assert_eq!(v, v.clone());
+        v.unwrap_vector().unwrap().borrow_mut().clear();
+    }
+
+    /// `v` wrapped `n` times in collections made by `wrap`.
+    fn nest(n: usize, v: Val, wrap: fn(Val) -> Val) -> Val {
+        (0..n).fold(v, |v, _| wrap(v))
+    }
+
+    #[test]
+    fn test_deep_values() {
+        const DEPTH: usize = 100_000;
+        let wraps: [fn(Val) -> Val; 3] = [
+            |v| Val::List(vec![v].into()),
+            |v| Val::PVector(PVector::new().push(v)),
+            |v| Val::Map(PMap::new().insert(Val::Int(1), v)),
+        ];
+        for (wrap, open) in wraps.iter().zip(["(", "[", "{1 "]) {
+            let a = nest(DEPTH, Val::Int(0), *wrap);
+            let b = nest(DEPTH, Val::Int(0), *wrap);
+            assert_eq!(a, b);
+            assert_eq!(hash(&a), hash(&b));
+            assert_ne!(a, nest(DEPTH, Val::Int(1), *wrap));
+            assert!(a.to_string().starts_with(&open.repeat(3)));
+        }
+        // Vectors are equal only to themselves, so they're compared and
+        // hashed without looking inside.
+        let a = nest(DEPTH, Val::Int(0), |v| Val::vector(vec![v]));
+        assert!(a.to_string().starts_with("#(#(#("));
+    }
+
+    #[test]
+    fn test_list_rest_shares_storage() {
+        let l = List::from(vec![Val::Int(1), Val::Int(2), Val::Int(3)]);
+        let r = l.rest();
+        assert_eq!(*r, [Val::Int(2), Val::Int(3)]);
+        assert!(Rc::ptr_eq(&l.items, &r.items));
+        assert!(r.rest().rest().rest().is_empty());
+    }
 }